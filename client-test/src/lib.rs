@@ -2,8 +2,14 @@ tonic::include_proto!("helloworld");
 tonic::include_proto!("grpc.examples.echo");
 
 wasm_bindgen_test_configure!(run_in_browser);
+use core::time::Duration;
+use futures::{FutureExt, StreamExt};
+use prost::Message;
+use tonic::{body::BoxBody, codegen::http};
 use wasm_bindgen_test::*;
-use webtonic_client::Client;
+use webtonic_client::{
+    Client, ConnectionState, MockEndpoints, MockTransport, ReconnectPolicy, RetryPolicy,
+};
 
 #[wasm_bindgen_test]
 async fn hello_world() {
@@ -30,4 +36,399 @@ async fn echo_unary() {
     let response = client.unary_echo(request).await.unwrap().into_inner();
     assert_eq!(response.message, "Echo Test");
 }
+
+#[wasm_bindgen_test]
+async fn mock_hello_world() {
+    let (transport, mut server) = MockTransport::new();
+    let mut client = greeter_client::GreeterClient::new(Client::with_transport(transport));
+
+    let request = tonic::Request::new(HelloRequest {
+        name: "WebTonic".into(),
+    });
+    let script = async {
+        let call = server.next_call().await.unwrap();
+        assert_eq!(call.uri().path(), "/helloworld.Greeter/SayHello");
+        let request = HelloRequest::decode(call.into_body()).unwrap();
+        assert_eq!(request.name, "WebTonic");
+
+        server
+            .reply(&HelloReply {
+                message: "Hello WebTonic!".into(),
+            })
+            .await;
+    };
+
+    let (response, ()) = futures::join!(client.say_hello(request), script);
+    assert_eq!(response.unwrap().into_inner().message, "Hello WebTonic!");
+}
+
+#[wasm_bindgen_test]
+async fn mock_status() {
+    let (transport, mut server) = MockTransport::new();
+    let mut client = echo_client::EchoClient::new(Client::with_transport(transport));
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        server.next_call().await.unwrap();
+        server
+            .reply_status(tonic::Status::not_found("no echo"))
+            .await;
+    };
+
+    let (response, ()) = futures::join!(client.unary_echo(request), script);
+    let status = response.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    assert_eq!(status.message(), "no echo");
+}
+
+#[wasm_bindgen_test]
+async fn mock_disconnect() {
+    let (transport, mut server) = MockTransport::new();
+    let mut client = echo_client::EchoClient::new(Client::with_transport(transport));
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        server.next_call().await.unwrap();
+        server.disconnect(1001, "going away");
+    };
+
+    let (response, ()) = futures::join!(client.unary_echo(request), script);
+    let status = response.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unavailable);
+    assert!(status.message().contains("1001"));
+}
+
+#[wasm_bindgen_test]
+async fn mock_close() {
+    let (transport, mut server) = MockTransport::new();
+    let client = Client::with_transport(transport);
+    let mut echo = echo_client::EchoClient::new(client.clone());
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        server.next_call().await.unwrap();
+        client.close(4001, "done").await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap_err().code(), tonic::Code::Unavailable);
+    assert_eq!(
+        client.state(),
+        ConnectionState::Disconnected {
+            code: Some(4001),
+            reason: "done".to_string()
+        }
+    );
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    assert_eq!(
+        echo.unary_echo(request).await.unwrap_err().code(),
+        tonic::Code::Unavailable
+    );
+}
+
+#[wasm_bindgen_test]
+async fn mock_connect_lazy() {
+    let (endpoints, mut servers) = MockEndpoints::new(1);
+    let mut server = servers.remove(0);
+    let client = Client::with_mock_endpoints(endpoints);
+    let mut echo = echo_client::EchoClient::new(client.clone());
+
+    // The connection is opened by the first call
+    assert_eq!(client.state(), ConnectionState::Idle);
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        server.next_call().await.unwrap();
+        server
+            .reply(&EchoResponse {
+                message: "Echo Test".to_string(),
+            })
+            .await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap().into_inner().message, "Echo Test");
+    assert_eq!(client.state(), ConnectionState::Connected);
+}
+
+#[wasm_bindgen_test]
+async fn mock_retry_status() {
+    let (transport, mut server) = MockTransport::new();
+    let client = Client::with_transport(transport).retry(
+        RetryPolicy::new()
+            .initial_backoff(Duration::from_millis(1))
+            .methods(vec!["/grpc.examples.echo.Echo/UnaryEcho"]),
+    );
+    let mut echo = echo_client::EchoClient::new(client.clone());
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        server.next_call().await.unwrap();
+        server
+            .reply_status(tonic::Status::unavailable("overloaded"))
+            .await;
+
+        let retried = server.next_call().await.unwrap();
+        assert_eq!(retried.uri().path(), "/grpc.examples.echo.Echo/UnaryEcho");
+        server
+            .reply(&EchoResponse {
+                message: "Echo Test".to_string(),
+            })
+            .await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap().into_inner().message, "Echo Test");
+    assert_eq!(client.metrics().calls, 1);
+    assert_eq!(client.metrics().retries, 1);
+}
+
+#[wasm_bindgen_test]
+async fn mock_retry_gives_up() {
+    let (transport, mut server) = MockTransport::new();
+    let client = Client::with_transport(transport).retry(
+        RetryPolicy::new()
+            .max_attempts(2)
+            .initial_backoff(Duration::from_millis(1))
+            .all_methods(),
+    );
+    let mut echo = echo_client::EchoClient::new(client.clone());
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        for _ in 0..2 {
+            server.next_call().await.unwrap();
+            server
+                .reply_status(tonic::Status::unavailable("overloaded"))
+                .await;
+        }
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap_err().code(), tonic::Code::Unavailable);
+    assert_eq!(client.metrics().retries, 1);
+
+    // Codes, that are not retryable, fail the call right away
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        server.next_call().await.unwrap();
+        server
+            .reply_status(tonic::Status::not_found("no echo"))
+            .await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap_err().code(), tonic::Code::NotFound);
+    assert_eq!(client.metrics().retries, 1);
+}
+
+#[wasm_bindgen_test]
+async fn mock_failover() {
+    let (endpoints, mut servers) = MockEndpoints::new(2);
+    let (mut first, mut second) = (servers.remove(0), servers.remove(0));
+    let client = Client::with_mock_endpoints(endpoints).retry(RetryPolicy::new().all_methods());
+    let mut echo = echo_client::EchoClient::new(client.clone());
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        // The call is interrupted by the first endpoint and repeated on the second one
+        first.next_call().await.unwrap();
+        first.disconnect(1001, "going away");
+
+        second.next_call().await.unwrap();
+        second
+            .reply(&EchoResponse {
+                message: "Echo Test".to_string(),
+            })
+            .await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap().into_inner().message, "Echo Test");
+    assert_eq!(client.state(), ConnectionState::Connected);
+    assert_eq!(client.metrics().reconnects, 1);
+    assert_eq!(client.metrics().retries, 1);
+}
+
+#[wasm_bindgen_test]
+async fn mock_failover_refused() {
+    let (endpoints, mut servers) = MockEndpoints::new(2);
+    let mut second = servers.remove(1);
+    let client = Client::with_mock_endpoints(endpoints);
+    let mut echo = echo_client::EchoClient::new(client.clone());
+
+    // The first endpoint is down
+    drop(servers);
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        second.next_call().await.unwrap();
+        second
+            .reply(&EchoResponse {
+                message: "Echo Test".to_string(),
+            })
+            .await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap().into_inner().message, "Echo Test");
+    assert_eq!(client.state(), ConnectionState::Connected);
+}
+
+#[wasm_bindgen_test]
+async fn mock_close_while_reconnecting() {
+    let (endpoints, mut servers) = MockEndpoints::new(1);
+    let mut server = servers.remove(0);
+    let client = Client::with_mock_endpoints(endpoints)
+        .reconnect(ReconnectPolicy::new().initial_backoff(Duration::from_millis(1)));
+    let mut echo = echo_client::EchoClient::new(client.clone());
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        server.next_call().await.unwrap();
+        server
+            .reply(&EchoResponse {
+                message: "Echo Test".to_string(),
+            })
+            .await;
+    };
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert!(response.is_ok());
+
+    // The only endpoint is gone, so the next call keeps reconnecting until the client is closed
+    server.disconnect(1001, "going away");
+    let mut changes = client.state_changes();
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        while !matches!(
+            changes.next().await,
+            Some(ConnectionState::Reconnecting { .. })
+        ) {}
+        client.close(4001, "done").await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap_err().code(), tonic::Code::Unavailable);
+    assert_eq!(
+        client.state(),
+        ConnectionState::Disconnected {
+            code: Some(4001),
+            reason: "done".to_string()
+        }
+    );
+}
+
+#[wasm_bindgen_test]
+async fn mock_interceptor_headers() {
+    let (transport, mut server) = MockTransport::new();
+    let client = Client::with_transport(transport).with_interceptor(
+        |mut request: http::Request<BoxBody>| async move {
+            request
+                .headers_mut()
+                .insert("authorization", "Bearer token".parse().unwrap());
+            Ok(request)
+        },
+    );
+    let mut echo = echo_client::EchoClient::new(client);
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        let call = server.next_call().await.unwrap();
+        assert_eq!(call.headers()["authorization"], "Bearer token");
+        server
+            .reply(&EchoResponse {
+                message: "Echo Test".to_string(),
+            })
+            .await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap().into_inner().message, "Echo Test");
+}
+
+#[wasm_bindgen_test]
+async fn mock_interceptor_rejects() {
+    let (transport, mut server) = MockTransport::new();
+    let client = Client::with_transport(transport)
+        .with_interceptor(|_request: http::Request<BoxBody>| async {
+            Err(tonic::Status::unauthenticated("no token"))
+        })
+        .with_interceptor(|_request: http::Request<BoxBody>| async {
+            panic!("interceptor after a rejection was run")
+        });
+    let mut echo = echo_client::EchoClient::new(client);
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let status = echo.unary_echo(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    assert_eq!(status.message(), "no token");
+
+    // The rejected call never reached the server
+    assert!(server.next_call().now_or_never().is_none());
+}
+
+#[wasm_bindgen_test]
+async fn mock_interceptor_order() {
+    let (transport, mut server) = MockTransport::new();
+    let append = |name: &'static str| {
+        move |mut request: http::Request<BoxBody>| async move {
+            request
+                .headers_mut()
+                .append("x-interceptor", name.parse().unwrap());
+            Ok(request)
+        }
+    };
+    let client = Client::with_transport(transport)
+        .with_interceptor(append("first"))
+        .with_interceptor(append("second"));
+    let mut echo = echo_client::EchoClient::new(client);
+
+    let request = tonic::Request::new(EchoRequest {
+        message: "Echo Test".to_string(),
+    });
+    let script = async {
+        let call = server.next_call().await.unwrap();
+        let order: Vec<_> = call.headers().get_all("x-interceptor").iter().collect();
+        assert_eq!(order, ["first", "second"]);
+        server
+            .reply(&EchoResponse {
+                message: "Echo Test".to_string(),
+            })
+            .await;
+    };
+
+    let (response, ()) = futures::join!(echo.unary_echo(request), script);
+    assert_eq!(response.unwrap().into_inner().message, "Echo Test");
+}
+
 //}
@@ -1,6 +1,11 @@
+use std::{env, path::PathBuf};
+
 fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
     tonic_build::configure()
         .build_client(false)
+        .file_descriptor_set_path(out_dir.join("test_descriptor.bin"))
         .compile(
             &["../proto-test/helloworld.proto", "../proto-test/echo.proto"],
             &["../proto-test"],
@@ -3,10 +3,13 @@ use crate::greeter_server::{Greeter, GreeterServer};
 use core::pin::Pin;
 use futures::Stream;
 use tonic::{Request, Response, Status};
+use webtonic_server::ConnectionInfo;
 
 tonic::include_proto!("helloworld");
 tonic::include_proto!("grpc.examples.echo");
 
+const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("test_descriptor");
+
 #[derive(Default)]
 pub struct MyGreeter {}
 
@@ -16,7 +19,11 @@ impl Greeter for MyGreeter {
         &self,
         request: Request<HelloRequest>,
     ) -> Result<Response<HelloReply>, Status> {
-        println!("Got a request from {:?}", request.remote_addr());
+        let remote_addr = request
+            .extensions()
+            .get::<ConnectionInfo>()
+            .and_then(|info| info.remote_addr());
+        println!("Got a request from {:?}", remote_addr);
 
         let reply = HelloReply {
             message: format!("Hello {}!", request.into_inner().name),
@@ -71,9 +78,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let greeter = MyGreeter::default();
     let echo = MyEcho::default();
 
+    let (health, health_service) = webtonic_server::health::health_reporter();
+    health.set_serving::<GreeterServer<MyGreeter>>().await;
+    health.set_serving::<EchoServer<MyEcho>>().await;
+
+    let reflection = webtonic_server::reflection::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(webtonic_server::health::FILE_DESCRIPTOR_SET)
+        .build()?;
+
     //println!("GreeterServer listening on {}", addr);
 
     webtonic_server::Server::builder()
+        .healthz(health)
+        .http_fallback()
+        .metrics(webtonic_server::metrics::Metrics::new(), "/metrics")
+        .add_service(health_service)
+        .add_service(reflection)
         .add_service(GreeterServer::new(greeter))
         .add_service(EchoServer::new(echo))
         .serve(([127, 0, 0, 1], 8080))
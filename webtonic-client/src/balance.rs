@@ -0,0 +1,261 @@
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use futures::{future::LocalBoxFuture, FutureExt};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::watch;
+use wasm_bindgen_futures::spawn_local;
+use webtonic_proto::WebTonicError;
+
+use crate::{
+    connection::{Open, Settings},
+    console_log,
+    fetch::FetchTransport,
+    state::ConnectionState,
+    timer,
+    transport::{BrowserTransport, Transport},
+    websocket::WebSocketTransport,
+};
+
+/// The interval, in which failed endpoints are probed, if none is configured.
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How a [`Client`](crate::Client) with multiple endpoints picks the one to connect to.
+///
+/// Endpoints, that failed, are only picked once all other endpoints failed as well, until
+/// they answer a probe again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Balance {
+    /// Take turns, starting with the next endpoint on every connection. This is the default.
+    #[default]
+    RoundRobin,
+
+    /// Pick a random endpoint on every connection.
+    Random,
+
+    /// Pick the endpoint, that opened a connection the fastest.
+    ///
+    /// The latency is measured whenever a connection is opened, including the probes.
+    LowestLatency,
+}
+
+/// The health of a single endpoint.
+#[derive(Debug, Clone, Default)]
+struct Health {
+    failed: bool,
+
+    /// The time it took to open the last connection, in milliseconds.
+    latency: Option<f64>,
+}
+
+/// The endpoints of a connection and their health.
+#[derive(Debug)]
+pub(crate) struct Endpoints {
+    uris: Vec<String>,
+    settings: Settings,
+    next: AtomicUsize,
+    current: AtomicUsize,
+    health: Mutex<Vec<Health>>,
+}
+
+impl Endpoints {
+    /// Creates the endpoints of a connection.
+    ///
+    /// If there are multiple endpoints, the failed ones are probed in the background.
+    pub(crate) fn new(uris: Vec<String>, settings: Settings) -> Arc<Self> {
+        let health = vec![Health::default(); uris.len()];
+        let endpoints = Arc::new(Self {
+            uris,
+            settings,
+            next: AtomicUsize::new(0),
+            current: AtomicUsize::new(0),
+            health: Mutex::new(health),
+        });
+
+        if endpoints.len() > 1 {
+            let interval = endpoints
+                .settings
+                .probe_interval
+                .unwrap_or(DEFAULT_PROBE_INTERVAL);
+            Self::spawn_prober(Arc::downgrade(&endpoints), interval);
+        }
+        endpoints
+    }
+
+    /// Returns the number of endpoints.
+    fn len(&self) -> usize {
+        self.uris.len()
+    }
+
+    fn uri(&self, endpoint: usize) -> &str {
+        &self.uris[endpoint]
+    }
+
+    /// Returns the endpoints in the order, in which connecting to them should be tried.
+    ///
+    /// Healthy endpoints are ordered by the [`Balance`](Balance), followed by the failed ones.
+    fn candidates(&self) -> Vec<usize> {
+        let len = self.len();
+        if len == 0 {
+            return vec![];
+        }
+
+        let balance = self.settings.balance;
+        let start = match balance {
+            Balance::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % len,
+            Balance::Random => (js_sys::Math::random() * len as f64) as usize % len,
+            Balance::LowestLatency => 0,
+        };
+        let mut order: Vec<usize> = (0..len).map(|i| (start + i) % len).collect();
+
+        let health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        if balance == Balance::LowestLatency {
+            // Endpoints of unknown latency come last, such that they are measured eventually
+            order.sort_by(|a, b| {
+                let a = health[*a].latency.unwrap_or(f64::INFINITY);
+                let b = health[*b].latency.unwrap_or(f64::INFINITY);
+                a.total_cmp(&b)
+            });
+        }
+        order.sort_by_key(|endpoint| health[*endpoint].failed);
+        order
+    }
+
+    /// Records, that a connection to `endpoint` was opened after `latency` milliseconds.
+    fn succeeded(&self, endpoint: usize, latency: f64) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health[endpoint] = Health {
+            failed: false,
+            latency: Some(latency),
+        };
+    }
+
+    /// Records, that a connection to `endpoint` could not be opened or was lost.
+    fn failed(&self, endpoint: usize) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health[endpoint].failed = true;
+    }
+
+    fn failed_endpoints(&self) -> Vec<usize> {
+        let health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        (0..self.len()).filter(|i| health[*i].failed).collect()
+    }
+
+    /// Opens a connection to `endpoint` and records the outcome.
+    async fn connect(
+        &self,
+        endpoint: usize,
+        state: Arc<watch::Sender<ConnectionState>>,
+    ) -> Result<BrowserTransport, WebTonicError> {
+        let started = js_sys::Date::now();
+        let transport = self.open_transport(endpoint, state).await;
+
+        match &transport {
+            Ok(_) => self.succeeded(endpoint, js_sys::Date::now() - started),
+            Err(e) => {
+                console_log(&format!(
+                    "failed to connect to {}: {}",
+                    self.uri(endpoint),
+                    e
+                ));
+                self.failed(endpoint);
+            }
+        }
+        transport
+    }
+
+    /// Opens a websocket to `endpoint`, falling back to HTTP if the upgrade fails and the
+    /// fallback is enabled.
+    async fn open_transport(
+        &self,
+        endpoint: usize,
+        state: Arc<watch::Sender<ConnectionState>>,
+    ) -> Result<BrowserTransport, WebTonicError> {
+        let uri = self.uri(endpoint);
+        let settings = &self.settings;
+
+        let error = match WebSocketTransport::connect(
+            uri,
+            &settings.protocols,
+            settings.connect_timeout,
+            state,
+        )
+        .await
+        {
+            Ok(transport) => return Ok(BrowserTransport::WebSocket(transport)),
+            Err(e @ WebTonicError::InvalidUrl(_)) => return Err(e),
+            Err(e) => e,
+        };
+        if !settings.http_fallback {
+            return Err(error);
+        }
+
+        console_log(&format!(
+            "failed to open a websocket to {}: {}, falling back to HTTP",
+            uri, error
+        ));
+        FetchTransport::connect(uri, settings.connect_timeout)
+            .await
+            .map(BrowserTransport::Fetch)
+    }
+
+    /// Opens a connection to the first endpoint, that accepts it.
+    async fn connect_any(
+        &self,
+        state: &Arc<watch::Sender<ConnectionState>>,
+    ) -> Result<BrowserTransport, WebTonicError> {
+        let mut error = WebTonicError::ConnectionError("no endpoints given".to_string());
+        for endpoint in self.candidates() {
+            match self.connect(endpoint, state.clone()).await {
+                Ok(transport) => {
+                    self.current.store(endpoint, Ordering::Release);
+                    return Ok(transport);
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    /// Probes the failed endpoints every `interval` in the background, until the endpoints
+    /// are dropped.
+    fn spawn_prober(endpoints: Weak<Self>, interval: Duration) {
+        spawn_local(async move {
+            loop {
+                timer::sleep(interval).await;
+                let endpoints = match endpoints.upgrade() {
+                    Some(endpoints) => endpoints,
+                    None => break,
+                };
+
+                for endpoint in endpoints.failed_endpoints() {
+                    // The probe does not affect the state of the client
+                    let (state, _) = watch::channel(ConnectionState::Idle);
+                    if let Ok(mut transport) = endpoints.connect(endpoint, Arc::new(state)).await {
+                        console_log(&format!("endpoint {} is back", endpoints.uri(endpoint)));
+                        transport.close(1000, "probe");
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Open<BrowserTransport> for Endpoints {
+    fn open<'a>(
+        &'a self,
+        state: &'a Arc<watch::Sender<ConnectionState>>,
+    ) -> LocalBoxFuture<'a, Result<BrowserTransport, WebTonicError>> {
+        self.connect_any(state).boxed_local()
+    }
+
+    fn endpoints(&self) -> usize {
+        self.len()
+    }
+
+    fn lost(&self) {
+        // Prefer the other endpoints, until the lost one answers a probe again
+        self.failed(self.current.load(Ordering::Acquire));
+    }
+}
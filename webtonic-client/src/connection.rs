@@ -0,0 +1,327 @@
+use bytes::Bytes;
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use futures::{future, future::LocalBoxFuture, pin_mut, Future};
+use http::header::HeaderMap;
+use std::sync::Arc;
+use tokio::sync::{watch, Mutex, Notify};
+use tonic::metadata::MetadataMap;
+use webtonic_proto::{Reply, WebTonicError};
+
+use crate::{
+    balance::{Balance, Endpoints},
+    console_log,
+    interceptor::{RequestInterceptor, ResponseInterceptor},
+    metrics::Metrics,
+    reconnect::ReconnectPolicy,
+    retry::RetryPolicy,
+    state::ConnectionState,
+    timer,
+    transport::{BrowserTransport, Socket, Transport},
+};
+
+/// The settings of a single [`Client`](crate::Client) handle.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Options {
+    pub(crate) silence_timeout: Option<Duration>,
+    pub(crate) reconnect: Option<ReconnectPolicy>,
+    pub(crate) retry: Option<RetryPolicy>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) max_message_size: Option<usize>,
+    pub(crate) metadata: HeaderMap,
+    pub(crate) request_interceptors: Vec<RequestInterceptor>,
+    pub(crate) response_interceptors: Vec<ResponseInterceptor>,
+}
+
+impl Options {
+    /// Adds `metadata` to the default metadata, replacing existing entries of the same keys.
+    pub(crate) fn add_metadata(&mut self, metadata: MetadataMap) {
+        let metadata = metadata.into_headers();
+        for key in metadata.keys() {
+            self.metadata.remove(key);
+        }
+        for (key, value) in metadata.iter() {
+            self.metadata.append(key, value.clone());
+        }
+    }
+}
+
+/// The settings of the transport, which apply to every (re)connection.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Settings {
+    pub(crate) protocols: Vec<String>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) balance: Balance,
+    pub(crate) probe_interval: Option<Duration>,
+    pub(crate) http_fallback: bool,
+}
+
+/// Opens the transports of a connection.
+pub(crate) trait Open<T>: fmt::Debug + Send + Sync {
+    /// Opens a new transport, whose closure is reported to `state`.
+    fn open<'a>(
+        &'a self,
+        state: &'a Arc<watch::Sender<ConnectionState>>,
+    ) -> LocalBoxFuture<'a, Result<T, WebTonicError>>;
+
+    /// Returns the number of endpoints, the transports are opened to.
+    fn endpoints(&self) -> usize;
+
+    /// Informs, that the last opened transport was lost.
+    fn lost(&self);
+}
+
+/// The connection to a server, which is shared by all clones of a [`Client`](crate::Client).
+///
+/// The transport is replaced, when the connection is reestablished.
+#[derive(Debug)]
+pub(crate) struct Connection<T> {
+    opener: Option<Arc<dyn Open<T>>>,
+    socket: Mutex<Option<Socket<T>>>,
+    lazy: AtomicBool,
+    reconnecting: AtomicBool,
+    closed: AtomicBool,
+    /// Interrupts the pending call, once the connection is closed.
+    closing: Notify,
+    state: Arc<watch::Sender<ConnectionState>>,
+    state_rx: watch::Receiver<ConnectionState>,
+    pub(crate) metrics: Metrics,
+}
+
+impl Connection<BrowserTransport> {
+    /// Creates a connection to one of the servers at `uris`, that is opened by the first call.
+    pub(crate) fn lazy(uris: Vec<String>, settings: Settings) -> Self {
+        let endpoints: Arc<dyn Open<BrowserTransport>> = Endpoints::new(uris, settings);
+        Self::new(Some(endpoints), None)
+    }
+
+    /// Creates a connection to one of the servers at `uris` and opens it immediately.
+    pub(crate) async fn connect(
+        uris: Vec<String>,
+        settings: Settings,
+    ) -> Result<Self, WebTonicError> {
+        let mut connection = Self::lazy(uris, settings);
+        let socket = connection.open().await?;
+        *connection.socket.get_mut() = Some(socket);
+        connection.lazy.store(false, Ordering::Release);
+
+        Ok(connection)
+    }
+}
+
+impl<T: Transport> Connection<T> {
+    fn new(opener: Option<Arc<dyn Open<T>>>, transport: Option<T>) -> Self {
+        let initial = match transport {
+            Some(_) => ConnectionState::Connected,
+            None => ConnectionState::Idle,
+        };
+        let (state, state_rx) = watch::channel(initial);
+
+        Self {
+            lazy: AtomicBool::new(transport.is_none()),
+            opener,
+            socket: Mutex::new(transport.map(Socket::new)),
+            reconnecting: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            closing: Notify::new(),
+            state: Arc::new(state),
+            state_rx,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Creates a connection over an open `transport`, which is not reestablished once lost.
+    pub(crate) fn with_transport(transport: T) -> Self {
+        Self::new(None, Some(transport))
+    }
+
+    /// Creates a connection, that is opened by `opener` on the first call.
+    pub(crate) fn with_opener(opener: Arc<dyn Open<T>>) -> Self {
+        Self::new(Some(opener), None)
+    }
+
+    /// Opens the socket for the first time.
+    async fn open(&self) -> Result<Socket<T>, WebTonicError> {
+        let opener = self.opener.as_ref().ok_or_else(|| {
+            WebTonicError::ConnectionError("the transport can not be reopened".to_string())
+        })?;
+
+        let _ = self.state.send(ConnectionState::Connecting);
+        let transport = opener.open(&self.state).await;
+
+        let _ = self.state.send(match &transport {
+            Ok(_) => ConnectionState::Connected,
+            Err(e) => ConnectionState::Disconnected {
+                code: None,
+                reason: format!("failed to connect: {}", e),
+            },
+        });
+        transport.map(Socket::new)
+    }
+
+    /// Returns `true`, if the connection was closed by the client.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Returns the current [`ConnectionState`](ConnectionState).
+    pub(crate) fn state(&self) -> ConnectionState {
+        self.state_rx.borrow().clone()
+    }
+
+    /// Returns a receiver, that is notified about every change of the
+    /// [`ConnectionState`](ConnectionState).
+    pub(crate) fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        self.state_rx.clone()
+    }
+
+    /// Sends a call over the connection and waits for the reply.
+    ///
+    /// If the connection was lost and `options` allow it, it is reestablished first.
+    pub(crate) async fn send(
+        &self,
+        request: &Bytes,
+        options: &Options,
+    ) -> Result<Reply, WebTonicError> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(connection_closed());
+        }
+        if let Some(policy) = &options.reconnect {
+            if !policy.queue_calls && self.reconnecting.load(Ordering::Acquire) {
+                return Err(WebTonicError::ConnectionClosed {
+                    code: None,
+                    reason: "reconnecting".to_string(),
+                });
+            }
+        }
+
+        // Holding the lock while reconnecting queues all other calls
+        let mut socket = self.socket.lock().await;
+        let socket = match (socket.take(), &options.reconnect, &self.opener) {
+            (Some(open), _, _) if open.is_open() => socket.insert(open),
+            // A lazy connection is opened by the first call, until that succeeds
+            (_, _, _) if self.lazy.load(Ordering::Acquire) => {
+                let opened = self.unless_closed(self.open()).await??;
+                self.lazy.store(false, Ordering::Release);
+                socket.insert(opened)
+            }
+            (lost, policy, Some(opener)) => {
+                if lost.is_some() {
+                    opener.lost();
+                }
+
+                // With multiple endpoints, the connection fails over to the next one
+                let failover;
+                let policy = match policy {
+                    Some(policy) => policy,
+                    None if opener.endpoints() > 1 => {
+                        failover = ReconnectPolicy::new().max_attempts(1);
+                        &failover
+                    }
+                    None => return Err(connection_lost()),
+                };
+
+                self.reconnecting.store(true, Ordering::Release);
+                let reconnected = self.reconnect(opener.as_ref(), policy).await;
+                self.reconnecting.store(false, Ordering::Release);
+                socket.insert(Socket::new(reconnected?))
+            }
+            (_, _, None) => return Err(connection_lost()),
+        };
+
+        self.unless_closed(socket.call(request, options.silence_timeout))
+            .await?
+    }
+
+    /// Runs `future`, unless the connection is closed before it completes.
+    ///
+    /// # Returns
+    /// - The output of `future`.
+    /// - [`WebTonicError::ConnectionClosed`](WebTonicError::ConnectionClosed), if the
+    ///   connection is or gets closed.
+    async fn unless_closed<F: Future>(&self, future: F) -> Result<F::Output, WebTonicError> {
+        // Created before checking the flag, such that a concurrent close is not missed
+        let closing = self.closing.notified();
+        if self.closed.load(Ordering::Acquire) {
+            return Err(connection_closed());
+        }
+
+        pin_mut!(future, closing);
+        match future::select(future, closing).await {
+            future::Either::Left((output, _)) => Ok(output),
+            future::Either::Right(((), _)) => Err(connection_closed()),
+        }
+    }
+
+    /// Closes the connection, such that it is not reestablished.
+    ///
+    /// The pending call and all following calls fail.
+    pub(crate) async fn close(&self, code: u16, reason: &str) {
+        if self.closed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.closing.notify_waiters();
+
+        if let Some(mut socket) = self.socket.lock().await.take() {
+            socket.close(code, reason);
+        }
+        let _ = self.state.send(ConnectionState::Disconnected {
+            code: Some(code),
+            reason: reason.to_string(),
+        });
+    }
+
+    async fn reconnect(
+        &self,
+        opener: &dyn Open<T>,
+        policy: &ReconnectPolicy,
+    ) -> Result<T, WebTonicError> {
+        let mut failed = 0;
+        loop {
+            // Closing the client stops reconnecting, also while waiting for the backoff
+            let attempt = async {
+                timer::sleep(policy.backoff(failed)).await;
+                let _ = self.state.send(ConnectionState::Reconnecting {
+                    attempt: failed + 1,
+                });
+                opener.open(&self.state).await
+            };
+
+            match self.unless_closed(attempt).await? {
+                Ok(transport) => {
+                    let _ = self.state.send(ConnectionState::Connected);
+                    self.metrics.reconnected();
+                    return Ok(transport);
+                }
+                Err(e) => {
+                    failed += 1;
+                    console_log(&format!("reconnect attempt {} failed: {:?}", failed, e));
+                    if matches!(policy.max_attempts, Some(max) if failed >= max) {
+                        let _ = self.state.send(ConnectionState::Disconnected {
+                            code: None,
+                            reason: format!("reconnection failed after {} attempts", failed),
+                        });
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn connection_lost() -> WebTonicError {
+    WebTonicError::ConnectionClosed {
+        code: None,
+        reason: "the connection was lost".to_string(),
+    }
+}
+
+fn connection_closed() -> WebTonicError {
+    WebTonicError::ConnectionClosed {
+        code: None,
+        reason: "the client was closed".to_string(),
+    }
+}
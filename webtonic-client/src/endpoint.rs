@@ -0,0 +1,218 @@
+use core::time::Duration;
+use http::header::HeaderValue;
+use tonic::metadata::MetadataMap;
+use webtonic_proto::WebTonicError;
+
+use crate::{
+    connection::{Connection, Options, Settings},
+    Balance, Client, ReconnectPolicy, RetryPolicy,
+};
+
+/// The compression of the replies, that the client is willing to accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Replies are sent uncompressed. This is the default.
+    Identity,
+
+    /// Replies may be compressed with gzip.
+    Gzip,
+}
+
+/// A builder of a [`Client`](Client), modeled after tonic's
+/// [`Endpoint`](https://docs.rs/tonic/0.6.2/tonic/transport/struct.Endpoint.html).
+///
+/// # Example
+/// ```
+/// let client = Client::builder("wss://example.com")
+///     .connect_timeout(Duration::from_secs(5))
+///     .timeout(Duration::from_secs(30))
+///     .protocols(vec!["webtonic"])
+///     .connect()
+///     .await
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    uris: Vec<String>,
+    settings: Settings,
+    options: Options,
+}
+
+impl Endpoint {
+    /// Creates an [`Endpoint`](Endpoint) in default configuration.
+    ///
+    /// # Arguments
+    /// - `uri`: The uri to connect to.
+    ///   **Note**: The sceme is either `ws://` or `wss://`, depending wether encryption is used or not.
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self {
+            uris: vec![uri.into()],
+            settings: Settings::default(),
+            options: Options::default(),
+        }
+    }
+
+    /// Creates an [`Endpoint`](Endpoint), that connects to one of multiple servers.
+    ///
+    /// The client picks a server according to the [`Balance`](Endpoint::balance).
+    /// If the connection can not be made, the next server is tried.
+    /// If an open connection drops, the next call fails over to another server.
+    /// Servers, that failed, are probed in the background and used again once they answer.
+    ///
+    /// # Arguments
+    /// - `uris`: the uris of the servers
+    ///
+    /// # Example
+    /// ```
+    /// let client = Endpoint::from_uris(vec!["wss://eu.example.com", "wss://us.example.com"])
+    ///     .balance(Balance::LowestLatency)
+    ///     .connect()
+    ///     .await
+    ///     .unwrap();
+    /// ```
+    pub fn from_uris<I, S>(uris: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            uris: uris.into_iter().map(Into::into).collect(),
+            settings: Settings::default(),
+            options: Options::default(),
+        }
+    }
+
+    /// Set how the server to connect to is picked, if there are multiple.
+    pub fn balance(mut self, balance: Balance) -> Self {
+        self.settings.balance = balance;
+        self
+    }
+
+    /// Set the interval, in which failed servers are probed. Defaults to 30 seconds.
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.settings.probe_interval = Some(interval);
+        self
+    }
+
+    /// Fail the connection attempt, if the socket is not open after `timeout`.
+    ///
+    /// This also applies to every attempt made while [reconnecting](Endpoint::reconnect).
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Fall back to sending the calls as HTTP requests, if the websocket can not be opened.
+    ///
+    /// Some proxies block websocket upgrades.
+    /// If the upgrade fails, the client checks, whether the server accepts calls over HTTP,
+    /// and then sends every call with a [`FetchTransport`](crate::FetchTransport).
+    /// The server needs to enable this (see `webtonic_server::Server::http_fallback`).
+    /// The calls include the cookies of the page, so a server on another origin needs to
+    /// allow the origin of the page explicitly.
+    ///
+    /// Every reconnection tries the websocket first.
+    pub fn http_fallback(mut self) -> Self {
+        self.settings.http_fallback = true;
+        self
+    }
+
+    /// Set the default timeout of the calls.
+    ///
+    /// Calls, that do not set a timeout themselves (e.g. with
+    /// [`Request::set_timeout`](https://docs.rs/tonic/0.6.2/tonic/struct.Request.html#method.set_timeout)),
+    /// fail with `DEADLINE_EXCEEDED` (see [`WebTonicError::Timeout`](WebTonicError::Timeout)),
+    /// if they did not finish in time.
+    /// The timeout is also sent to the server as the `grpc-timeout` of the call.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Offer websocket subprotocols to the server.
+    ///
+    /// The server picks one of them, which is commonly used to transmit credentials
+    /// (see the `Authentication` section of [`Client`](Client)).
+    pub fn protocols<I, S>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.settings.protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Limit the size of the encoded calls and replies.
+    ///
+    /// Larger calls are not sent and larger replies are discarded, both failing with
+    /// `RESOURCE_EXHAUSTED` (see [`WebTonicError::MessageTooLarge`](WebTonicError::MessageTooLarge)).
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.options.max_message_size = Some(size);
+        self
+    }
+
+    /// Add metadata to every call.
+    ///
+    /// Metadata set on the call itself takes precedence.
+    pub fn metadata(mut self, metadata: MetadataMap) -> Self {
+        self.options.add_metadata(metadata);
+        self
+    }
+
+    /// Tell the server, which compression the client accepts for the replies.
+    ///
+    /// This sets the `grpc-accept-encoding` of every call.
+    ///
+    /// **Note**: The generated client needs to be able to decompress the replies, e.g. by enabling
+    /// the `compression` feature of tonic and calling `accept_gzip()` on it.
+    pub fn accept_compression(mut self, compression: Compression) -> Self {
+        let encoding = match compression {
+            Compression::Identity => "identity",
+            Compression::Gzip => "gzip",
+        };
+        self.options
+            .metadata
+            .insert("grpc-accept-encoding", HeaderValue::from_static(encoding));
+        self
+    }
+
+    /// Consider the server dead, if it does not answer a call in time
+    /// (see [`Client::silence_timeout`](Client::silence_timeout)).
+    pub fn silence_timeout(mut self, timeout: Duration) -> Self {
+        self.options.silence_timeout = Some(timeout);
+        self
+    }
+
+    /// Reestablish the connection, if it was lost (see [`Client::reconnect`](Client::reconnect)).
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.options.reconnect = Some(policy);
+        self
+    }
+
+    /// Retry failed calls (see [`Client::retry`](Client::retry)).
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.options.retry = Some(policy);
+        self
+    }
+
+    /// Connects the client to the endpoint.
+    ///
+    /// # Returns
+    /// - A [`Client`](Client) on success.
+    /// - [`WebTonicError::InvalidUrl`](WebTonicError::InvalidUrl), if the url is malformed.
+    /// - [`WebTonicError::ConnectionError`](WebTonicError::ConnectionError), if the endpoint can
+    ///   not be reached.
+    pub async fn connect(self) -> Result<Client<'static>, WebTonicError> {
+        let connection = Connection::connect(self.uris, self.settings).await?;
+        Ok(Client::new(connection, self.options))
+    }
+
+    /// Creates the client without connecting.
+    ///
+    /// The connection is opened by the first call.
+    /// If that fails, the call fails and the next call tries again.
+    pub fn connect_lazy(self) -> Client<'static> {
+        let connection = Connection::lazy(self.uris, self.settings);
+        Client::new(connection, self.options)
+    }
+}
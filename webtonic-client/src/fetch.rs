@@ -0,0 +1,251 @@
+use bytes::Bytes;
+use core::{fmt, time::Duration};
+use futures::{future, future::LocalBoxFuture, pin_mut, FutureExt};
+use js_sys::{Promise, Uint8Array};
+use prost::Message;
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AbortController, Headers, Request, RequestCredentials, RequestInit, Response};
+use webtonic_proto::{Call, Reply, WebTonicError, HTTP_CONTENT_TYPE};
+
+use crate::{console_log, timer, transport::Transport, websocket::js_error_message};
+
+#[wasm_bindgen]
+extern "C" {
+    // Bound directly on the global object, such that it also works in web workers
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_with_request(request: &Request) -> Promise;
+}
+
+/// The reply to a call, that was sent as a HTTP request.
+type PendingRequest = LocalBoxFuture<'static, Result<Bytes, WebTonicError>>;
+
+/// A [`Transport`](Transport), that sends every call as a HTTP `POST` request, using the
+/// `fetch` API of the browser.
+///
+/// This is slower than a websocket, but works behind proxies, that block websocket upgrades.
+/// The server needs to accept calls over HTTP (see `webtonic_server::Server::http_fallback`).
+/// Like over a websocket, the reply to a server streaming call arrives once the stream ended.
+///
+/// A [`Client`](crate::Client) falls back to this transport automatically, if enabled with
+/// [`Endpoint::http_fallback`](crate::Endpoint::http_fallback).
+/// It can also be used directly:
+///
+/// # Example
+/// ```
+/// let transport = FetchTransport::new("https://example.com").unwrap();
+/// let client = Client::with_transport(transport);
+/// ```
+pub struct FetchTransport {
+    uri: String,
+    abort: AbortController,
+
+    /// The requests in flight, in the order in which they were sent.
+    pending: VecDeque<PendingRequest>,
+    closed: bool,
+}
+
+impl FetchTransport {
+    /// Creates a [`FetchTransport`](FetchTransport) to the server at `uri`.
+    ///
+    /// No request is made before the first call.
+    ///
+    /// # Arguments
+    /// - `uri`: the uri of the server, websocket uris are translated to the matching
+    ///   `http://` or `https://` uri
+    ///
+    /// # Returns
+    /// - The [`FetchTransport`](FetchTransport) on success.
+    /// - [`WebTonicError::InvalidUrl`](WebTonicError::InvalidUrl), if the uri has an unsupported
+    ///   scheme.
+    pub fn new(uri: &str) -> Result<Self, WebTonicError> {
+        let uri = http_uri(uri)?;
+        let abort = AbortController::new()
+            .map_err(|e| WebTonicError::ConnectionError(js_error_message(&e)))?;
+
+        Ok(Self {
+            uri,
+            abort,
+            pending: VecDeque::new(),
+            closed: false,
+        })
+    }
+
+    /// Creates a [`FetchTransport`](FetchTransport) to `uri` and checks, that the server
+    /// accepts calls over HTTP.
+    pub(crate) async fn connect(
+        uri: &str,
+        connect_timeout: Option<Duration>,
+    ) -> Result<Self, WebTonicError> {
+        let transport = Self::new(uri)?;
+
+        // The server answers a probe like a call, so it also passes the CORS checks of the calls
+        let probe = transport.request(&Call::probe().encode_to_vec());
+        let probed = match connect_timeout {
+            Some(timeout) => {
+                let timer = timer::sleep(timeout);
+                pin_mut!(timer);
+                match future::select(probe, timer).await {
+                    future::Either::Left((probed, _)) => probed,
+                    future::Either::Right(((), _)) => {
+                        return Err(WebTonicError::ConnectionError(format!(
+                            "not connected after {:?}",
+                            timeout
+                        )));
+                    }
+                }
+            }
+            None => probe.await,
+        };
+
+        let reply = Reply::decode(probed?).map_err(|e| WebTonicError::DecodingError(Some(e)))?;
+        if reply.grpc_status().is_none() {
+            return Err(WebTonicError::ConnectionError(
+                "server does not accept calls over HTTP".to_string(),
+            ));
+        }
+
+        Ok(transport)
+    }
+
+    /// Starts a request, that posts `body` to the server.
+    ///
+    /// # Returns
+    /// - The body of the response, once it arrived.
+    /// - An error, if the request failed or the server answered with an error.
+    fn request(&self, body: &[u8]) -> PendingRequest {
+        // The request is made right away, not when the future is first polled
+        let started = self.start(body);
+
+        async move {
+            let response = JsFuture::from(started?).await.map_err(fetch_error)?;
+            let response: Response = response
+                .dyn_into()
+                .map_err(|_| WebTonicError::ConnectionError("invalid response".to_string()))?;
+            if !response.ok() {
+                return Err(WebTonicError::ConnectionError(format!(
+                    "server answered with HTTP status {}",
+                    response.status()
+                )));
+            }
+
+            let body = response.array_buffer().map_err(fetch_error)?;
+            let body = JsFuture::from(body).await.map_err(fetch_error)?;
+            Ok(Bytes::from(Uint8Array::new(&body).to_vec()))
+        }
+        .boxed_local()
+    }
+
+    fn start(&self, body: &[u8]) -> Result<Promise, WebTonicError> {
+        let init = RequestInit::new();
+        init.set_method("POST");
+        // Send the cookies, such that the server can authenticate the calls
+        init.set_credentials(RequestCredentials::Include);
+        init.set_signal(Some(&self.abort.signal()));
+
+        let headers = Headers::new().map_err(fetch_error)?;
+        headers
+            .set("content-type", HTTP_CONTENT_TYPE)
+            .map_err(fetch_error)?;
+        init.set_headers(&headers);
+        init.set_body(&Uint8Array::from(body));
+
+        let request = Request::new_with_str_and_init(&self.uri, &init)
+            .map_err(|e| WebTonicError::InvalidUrl(js_error_message(&e)))?;
+        Ok(fetch_with_request(&request))
+    }
+}
+
+impl Transport for FetchTransport {
+    fn send(&mut self, frame: Bytes) -> Result<(), WebTonicError> {
+        if self.closed {
+            return Err(WebTonicError::ConnectionClosed {
+                code: None,
+                reason: "transport was closed".to_string(),
+            });
+        }
+
+        let request = self.request(&frame);
+        self.pending.push_back(request);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> LocalBoxFuture<'_, Result<Bytes, WebTonicError>> {
+        async move {
+            // The request stays queued until it finished, such that the reply of an abandoned
+            // call is skipped by the next one
+            let reply = match self.pending.front_mut() {
+                Some(request) => request.await,
+                None => {
+                    return Err(WebTonicError::ConnectionError(
+                        "no call in flight".to_string(),
+                    ))
+                }
+            };
+            self.pending.pop_front();
+
+            // Reopening the transport checks, whether the server is still reachable
+            if let Err(e) = &reply {
+                console_log(&format!("request to {} failed: {}", self.uri, e));
+                self.closed = true;
+            }
+            reply
+        }
+        .boxed_local()
+    }
+
+    fn close(&mut self, _code: u16, _reason: &str) {
+        self.closed = true;
+        self.abort.abort();
+        self.pending.clear();
+    }
+
+    fn is_open(&self) -> bool {
+        !self.closed
+    }
+}
+
+// Abort the requests in flight once the transport gets dropped
+impl Drop for FetchTransport {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            self.abort.abort();
+        }
+    }
+}
+
+impl fmt::Debug for FetchTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FetchTransport")
+            .field("uri", &self.uri)
+            .field("pending", &self.pending.len())
+            .field("closed", &self.closed)
+            .finish()
+    }
+}
+
+/// Translates the uri of a server to the uri, to which the calls are sent over HTTP.
+fn http_uri(uri: &str) -> Result<String, WebTonicError> {
+    let (scheme, rest) = uri
+        .split_once("://")
+        .ok_or_else(|| WebTonicError::InvalidUrl(format!("{:?} has no scheme", uri)))?;
+
+    let scheme = match scheme.to_ascii_lowercase().as_str() {
+        "ws" | "http" => "http",
+        "wss" | "https" => "https",
+        scheme => {
+            return Err(WebTonicError::InvalidUrl(format!(
+                "unsupported scheme {:?}",
+                scheme
+            )))
+        }
+    };
+    Ok(format!("{}://{}", scheme, rest))
+}
+
+/// The error of a request, that failed in the browser.
+fn fetch_error(e: JsValue) -> WebTonicError {
+    WebTonicError::ConnectionError(js_error_message(&e))
+}
@@ -0,0 +1,72 @@
+use core::{fmt, future::Future};
+use futures::{future::LocalBoxFuture, FutureExt};
+use http::request::Request;
+use std::sync::Arc;
+use tonic::{body::BoxBody, metadata::MetadataMap, Status};
+
+type RequestCallback =
+    dyn Fn(Request<BoxBody>) -> LocalBoxFuture<'static, Result<Request<BoxBody>, Status>>;
+type ResponseCallback = dyn Fn(&MetadataMap, &MetadataMap) -> Result<(), Status>;
+
+/// An asynchronous callback, that is run on every outgoing call of a [`Client`](crate::Client).
+#[derive(Clone)]
+pub(crate) struct RequestInterceptor(Arc<RequestCallback>);
+
+impl RequestInterceptor {
+    pub(crate) fn new<F, Fut>(f: F) -> Self
+    where
+        F: Fn(Request<BoxBody>) -> Fut + 'static,
+        Fut: Future<Output = Result<Request<BoxBody>, Status>> + 'static,
+    {
+        Self(Arc::new(move |request| f(request).boxed_local()))
+    }
+
+    /// Runs the callback on the request.
+    ///
+    /// # Returns
+    /// - `Ok(request)`, with the possibly modified request, if it may be sent
+    /// - `Err(status)`, if the call was rejected
+    pub(crate) async fn intercept(
+        &self,
+        request: Request<BoxBody>,
+    ) -> Result<Request<BoxBody>, Status> {
+        (self.0)(request).await
+    }
+}
+
+impl fmt::Debug for RequestInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestInterceptor")
+    }
+}
+
+/// A callback, that inspects the headers and trailers of every reply of a
+/// [`Client`](crate::Client).
+#[derive(Clone)]
+pub(crate) struct ResponseInterceptor(Arc<ResponseCallback>);
+
+impl ResponseInterceptor {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&MetadataMap, &MetadataMap) -> Result<(), Status> + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    /// Runs the callback on the `headers` and `trailers` of a reply.
+    // Returning the `Status` unboxed matches tonic's own interceptors
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn intercept(
+        &self,
+        headers: &MetadataMap,
+        trailers: &MetadataMap,
+    ) -> Result<(), Status> {
+        (self.0)(headers, trailers)
+    }
+}
+
+impl fmt::Debug for ResponseInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResponseInterceptor")
+    }
+}
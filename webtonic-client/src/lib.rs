@@ -3,22 +3,61 @@
 //! This crate only contains the [`Client`](Client), which requires a browser runtime
 //! to function.
 
+mod balance;
+mod connection;
+mod endpoint;
+mod fetch;
+mod interceptor;
+mod metrics;
+mod mock;
+mod reconnect;
+mod retry;
+mod state;
+mod timer;
+mod transport;
 mod websocket;
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use core::{
+    future::Future,
     marker::PhantomData,
     task::{Context, Poll},
+    time::Duration,
+};
+use futures::{
+    future::{self, LocalBoxFuture},
+    pin_mut, FutureExt, Stream, StreamExt,
+};
+use http::{
+    header::{HeaderMap, HeaderValue},
+    request::Request,
+    response::Response,
 };
-use futures::{future::LocalBoxFuture, FutureExt};
-use http::{request::Request, response::Response};
 use prost::Message;
-use tonic::{body::BoxBody, client::GrpcService};
+use std::sync::Arc;
+use tonic::{body::BoxBody, client::GrpcService, metadata::MetadataMap, Status};
 use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::spawn_local;
 use web_sys::console;
 use webtonic_proto::{Reply, WebTonicError};
 
-use crate::websocket::WebSocketConnector;
+pub use crate::balance::Balance;
+pub use crate::endpoint::{Compression, Endpoint};
+pub use crate::fetch::FetchTransport;
+pub use crate::metrics::ClientMetrics;
+pub use crate::mock::{MockEndpoints, MockServer, MockTransport};
+pub use crate::reconnect::ReconnectPolicy;
+pub use crate::retry::RetryPolicy;
+pub use crate::state::ConnectionState;
+pub use crate::transport::{BrowserTransport, Transport};
+pub use crate::websocket::WebSocketTransport;
+use crate::{
+    connection::{Connection, Options},
+    interceptor::{RequestInterceptor, ResponseInterceptor},
+};
+
+/// The number of parts of a streamed reply, that the server may send ahead of the client.
+const STREAM_CREDITS: u32 = 16;
 
 pub(crate) fn console_log(s: &str) {
     console::log_1(&JsValue::from_str(s));
@@ -34,7 +73,61 @@ pub(crate) fn console_log(s: &str) {
 /// # Cryptography
 /// This transport implementation does not directly support encryption.
 /// It is however possible to encrypt the websocket connection itself.
-/// However, client authentication is not possible that way.
+///
+/// # Keepalive
+/// Browsers answer the websocket pings of the server automatically.
+/// The server can therefore detect dead clients on its own (see `webtonic_server::Server::keepalive`).
+/// To detect a dead server, the client can be given a [silence timeout](Client::silence_timeout).
+///
+/// # Reconnection
+/// By default, a lost connection stays lost and all further calls fail.
+/// With a [`ReconnectPolicy`](ReconnectPolicy), the next call reestablishes the connection instead
+/// (see [`Client::reconnect`](Client::reconnect)).
+/// All clones of a [`Client`](Client) share the connection, so clones handed to generated
+/// clients keep working after a reconnect.
+///
+/// # Multiple endpoints
+/// A client can be [created](Endpoint::from_uris) with the uris of multiple servers, e.g. in
+/// different regions.
+/// It connects to one of them, picked according to a [`Balance`](Balance), and fails over to the
+/// others, if that server can not be reached or the connection drops.
+///
+/// # Retries
+/// Calls, that are safe to repeat, can be retried after they failed, according to a
+/// [`RetryPolicy`](RetryPolicy) (see [`Client::retry`](Client::retry)).
+/// The retries are counted in the [metrics](Client::metrics) of the client.
+///
+/// # Interceptors
+/// Metadata, that is sent with every call (e.g. a tenant ID), can be set once on the client
+/// with [`Client::metadata`](Client::metadata).
+/// Metadata, that changes (e.g. an auth token, that needs refreshing), can be added by an
+/// asynchronous [request interceptor](Client::with_interceptor).
+/// Interceptors are run for every call made through the client, so the generated clients do not
+/// need to be wrapped individually.
+///
+/// # Errors
+/// Calls, that fail in the transport, fail with a [`Status`](Status) converted from the
+/// [`WebTonicError`](WebTonicError), e.g. `UNAVAILABLE` if the connection was lost or `INTERNAL`
+/// if the reply could not be decoded.
+/// The message of the [`Status`](Status) describes the failure, including the close code of
+/// the websocket.
+/// Replies without a `grpc-status` (e.g. produced by a proxy in front of the server) are mapped
+/// to a [`Status`](Status) based on their HTTP status.
+///
+/// # Transports
+/// By default, the client tunnels the calls through a [`WebSocketTransport`](WebSocketTransport).
+/// Behind proxies, that block websockets, it can fall back to sending every call as a HTTP
+/// request with a [`FetchTransport`](FetchTransport) (see
+/// [`Endpoint::http_fallback`](Endpoint::http_fallback)).
+/// Any other [`Transport`](Transport) can be used with [`Client::with_transport`](Client::with_transport).
+/// In particular, the [`MockTransport`](MockTransport) allows testing browser code without a
+/// running server.
+///
+/// # Authentication
+/// Browsers do not allow setting arbitrary headers on websocket connections.
+/// Clients can instead be authenticated by the server when the connection is upgraded,
+/// using cookies, the query string of the `uri` or the `Sec-WebSocket-Protocol` header.
+/// See `webtonic_server::Server::authenticate`.
 ///
 /// # Example
 /// Assuming we have the
@@ -52,12 +145,23 @@ pub(crate) fn console_log(s: &str) {
 /// let response = client.say_hello(request).await.unwrap().into_inner();
 /// assert_eq!(response.message, "Hello WebTonic!");
 /// ```
-#[derive(Debug, Clone)]
-pub struct Client<'a> {
-    ws: WebSocketConnector,
+#[derive(Debug)]
+pub struct Client<'a, T = BrowserTransport> {
+    connection: Arc<Connection<T>>,
+    options: Options,
     _a: PhantomData<&'a ()>,
 }
 
+impl<'a, T> Clone for Client<'a, T> {
+    fn clone(&self) -> Self {
+        Self {
+            connection: self.connection.clone(),
+            options: self.options.clone(),
+            _a: PhantomData,
+        }
+    }
+}
+
 impl Client<'static> {
     /// Connects the client to the endpoint.
     ///
@@ -75,18 +179,282 @@ impl Client<'static> {
     /// let client = Client::connect("ws://localhost:1337").await.unwrap();
     /// ```
     pub async fn connect(uri: &str) -> Result<Self, WebTonicError> {
-        let ws = WebSocketConnector::connect(uri).await?;
-        Ok(Self {
-            ws,
+        Endpoint::new(uri).connect().await
+    }
+
+    /// Create an [`Endpoint`](Endpoint), to configure the client before connecting.
+    ///
+    /// # Example
+    /// ```
+    /// let client = Client::builder("ws://localhost:1337")
+    ///     .timeout(Duration::from_secs(10))
+    ///     .connect_lazy();
+    /// ```
+    pub fn builder(uri: impl Into<String>) -> Endpoint {
+        Endpoint::new(uri)
+    }
+}
+
+impl<T: Transport> Client<'static, T> {
+    /// Creates a client, that tunnels its calls through an open `transport`.
+    ///
+    /// The connection is not reestablished, once the transport is closed.
+    ///
+    /// # Arguments
+    /// - `transport`: the [`Transport`](Transport) to use
+    ///
+    /// # Example
+    /// ```
+    /// let (transport, server) = MockTransport::new();
+    /// let client = Client::with_transport(transport);
+    /// ```
+    pub fn with_transport(transport: T) -> Self {
+        let connection = Connection::with_transport(transport);
+        Self::new(connection, Options::default())
+    }
+}
+
+impl Client<'static, MockTransport> {
+    /// Creates a client, that connects to one of the scripted `endpoints` on its first call
+    /// and fails over to the next one, once the connection is lost.
+    ///
+    /// # Arguments
+    /// - `endpoints`: the [`MockEndpoints`](MockEndpoints) to connect to
+    ///
+    /// # Example
+    /// ```
+    /// let (endpoints, servers) = MockEndpoints::new(2);
+    /// let client = Client::with_mock_endpoints(endpoints);
+    /// ```
+    pub fn with_mock_endpoints(endpoints: MockEndpoints) -> Self {
+        let connection = Connection::with_opener(Arc::new(endpoints));
+        Self::new(connection, Options::default())
+    }
+}
+
+impl<'a, T: Transport> Client<'a, T> {
+    pub(crate) fn new(connection: Connection<T>, options: Options) -> Self {
+        Self {
+            connection: Arc::new(connection),
+            options,
             _a: PhantomData,
-        })
+        }
+    }
+
+    /// Consider the server dead, if it does not answer a call in time.
+    ///
+    /// Browsers do not expose the websocket pings to the page, so a server, that went silent
+    /// (e.g. because of a dropped NAT mapping), can otherwise only be detected once the
+    /// operating system gives up on the TCP connection.
+    /// If no reply arrives within `timeout`, the connection is closed with the close code `4000`
+    /// and the call, as well as all following calls, fail with `UNAVAILABLE`
+    /// (see [`WebTonicError::ConnectionClosed`](WebTonicError::ConnectionClosed)).
+    ///
+    /// **Note**: The timeout must be longer than the slowest call made over the connection.
+    ///
+    /// # Arguments
+    /// - `timeout`: the maximum time to wait for a reply
+    ///
+    /// # Returns
+    /// - The [`Client`](Client) with the silence timeout set.
+    pub fn silence_timeout(mut self, timeout: Duration) -> Self {
+        self.options.silence_timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the current [`ConnectionState`](ConnectionState) of the client.
+    pub fn state(&self) -> ConnectionState {
+        self.connection.state()
+    }
+
+    /// Subscribe to the changes of the [`ConnectionState`](ConnectionState).
+    ///
+    /// The stream yields every state, that is entered after the subscription.
+    /// If the state changes faster than the stream is polled, intermediate states are skipped.
+    ///
+    /// # Example
+    /// ```
+    /// let mut states = client.state_changes();
+    /// while let Some(state) = states.next().await {
+    ///     show_connection_state(state.name());
+    /// }
+    /// ```
+    pub fn state_changes(&self) -> impl Stream<Item = ConnectionState> + Unpin + 'static {
+        state::changes(self.connection.subscribe())
+    }
+
+    /// Call a JS function on every change of the [`ConnectionState`](ConnectionState).
+    ///
+    /// The function is called with an object of the form `{ state, attempt, code, reason }`
+    /// (see [`ConnectionState::to_js`](ConnectionState::to_js)).
+    ///
+    /// # Arguments
+    /// - `callback`: the JS function to call
+    pub fn on_state_change(&self, callback: js_sys::Function) {
+        let mut states = self.state_changes();
+        spawn_local(async move {
+            while let Some(state) = states.next().await {
+                if let Err(e) = callback.call1(&JsValue::NULL, &state.to_js()) {
+                    console_log(&format!("state change callback failed {:?}", e));
+                }
+            }
+        });
+    }
+
+    /// Reestablish the connection, if it was lost.
+    ///
+    /// The reconnection is started by the first call after the connection was lost.
+    /// Whether calls made in the meantime wait for the new connection or fail, is decided by the
+    /// [`ReconnectPolicy`](ReconnectPolicy).
+    ///
+    /// **Note**: A call, that was in flight when the connection was lost, still fails with
+    /// `UNAVAILABLE` (see [`WebTonicError::ConnectionClosed`](WebTonicError::ConnectionClosed)),
+    /// since it is unknown whether the server processed it.
+    ///
+    /// # Arguments
+    /// - `policy`: the [`ReconnectPolicy`](ReconnectPolicy) to apply
+    ///
+    /// # Returns
+    /// - The [`Client`](Client) with reconnection enabled.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.options.reconnect = Some(policy);
+        self
+    }
+
+    /// Retry failed calls.
+    ///
+    /// The call is only sent once and the attempts share its timeout.
+    /// Together with a [`ReconnectPolicy`](ReconnectPolicy), a call, that was in flight when the
+    /// connection was lost, is repeated once the connection is reestablished.
+    ///
+    /// # Arguments
+    /// - `policy`: the [`RetryPolicy`](RetryPolicy) to apply
+    ///
+    /// # Returns
+    /// - The [`Client`](Client) with retries enabled.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.options.retry = Some(policy);
+        self
+    }
+
+    /// Close the connection to the server.
+    ///
+    /// The connection is shared by all clones of the client, so they are closed as well.
+    /// The pending call and all following calls fail with `UNAVAILABLE`, and the connection
+    /// is not reestablished.
+    /// Closing a closed client has no effect.
+    ///
+    /// Dropping the client and all of its clones closes the connection with code `1000`, too.
+    ///
+    /// **Note**: Browsers only accept the code `1000` and codes from `3000` to `4999`,
+    /// other codes are replaced by `1000`.
+    ///
+    /// # Arguments
+    /// - `code`: the websocket close code to send
+    /// - `reason`: a human readable reason of the closure
+    ///
+    /// # Example
+    /// ```
+    /// client.close(1000, "user logged out").await;
+    /// assert!(matches!(client.state(), ConnectionState::Disconnected { .. }));
+    /// ```
+    pub async fn close(&self, code: u16, reason: &str) {
+        self.connection.close(code, reason).await
+    }
+
+    /// Returns the current [`ClientMetrics`](ClientMetrics) of the client.
+    ///
+    /// The counters are shared by all clones of the client.
+    pub fn metrics(&self) -> ClientMetrics {
+        self.connection.metrics.snapshot()
+    }
+
+    /// Add metadata to every call.
+    ///
+    /// Metadata set on the call itself or by an [interceptor](Client::with_interceptor) takes
+    /// precedence.
+    ///
+    /// # Arguments
+    /// - `metadata`: the metadata to add
+    ///
+    /// # Returns
+    /// - The [`Client`](Client) with the default metadata set.
+    pub fn metadata(mut self, metadata: MetadataMap) -> Self {
+        self.options.add_metadata(metadata);
+        self
+    }
+
+    /// Run an asynchronous interceptor on every outgoing call.
+    ///
+    /// The interceptor receives the request after the [default metadata](Client::metadata) was
+    /// added, and returns the request to send.
+    /// If it returns an error instead, the call is not sent and fails with the returned
+    /// [`Status`](Status).
+    /// Multiple interceptors are run in the order they were added.
+    ///
+    /// # Arguments
+    /// - `interceptor`: the function to run on the requests
+    ///
+    /// # Returns
+    /// - The [`Client`](Client) with the interceptor added.
+    ///
+    /// # Example
+    /// ```
+    /// let client = client.with_interceptor(move |mut request: http::Request<BoxBody>| {
+    ///     let tokens = tokens.clone();
+    ///     async move {
+    ///         let token = tokens.fresh_token().await.map_err(|_| Status::unauthenticated(""))?;
+    ///         request.headers_mut().insert("authorization", token);
+    ///         Ok(request)
+    ///     }
+    /// });
+    /// ```
+    pub fn with_interceptor<F, Fut>(mut self, interceptor: F) -> Self
+    where
+        F: Fn(Request<BoxBody>) -> Fut + 'static,
+        Fut: Future<Output = Result<Request<BoxBody>, Status>> + 'static,
+    {
+        self.options
+            .request_interceptors
+            .push(RequestInterceptor::new(interceptor));
+        self
+    }
+
+    /// Run an interceptor on the headers and trailers of every reply.
+    ///
+    /// If the interceptor returns an error, the call fails with the returned
+    /// [`Status`](Status), even if the server answered it successfully.
+    ///
+    /// # Arguments
+    /// - `interceptor`: the function to run on the headers and the trailers of the replies
+    ///
+    /// # Returns
+    /// - The [`Client`](Client) with the interceptor added.
+    ///
+    /// # Example
+    /// ```
+    /// let client = client.with_response_interceptor(|_headers, trailers| {
+    ///     if let Some(usage) = trailers.get("x-quota-remaining") {
+    ///         console_log(&format!("remaining quota: {:?}", usage));
+    ///     }
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn with_response_interceptor<F>(mut self, interceptor: F) -> Self
+    where
+        F: Fn(&MetadataMap, &MetadataMap) -> Result<(), Status> + 'static,
+    {
+        self.options
+            .response_interceptors
+            .push(ResponseInterceptor::new(interceptor));
+        self
     }
 }
 
-impl<'a> GrpcService<BoxBody> for Client<'a> {
+impl<'a, T: Transport> GrpcService<BoxBody> for Client<'a, T> {
     type ResponseBody = BoxBody;
-    type Error = WebTonicError;
-    type Future = LocalBoxFuture<'a, Result<Response<BoxBody>, WebTonicError>>;
+    type Error = Status;
+    type Future = LocalBoxFuture<'a, Result<Response<BoxBody>, Status>>;
 
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         // We return an ok, because we are essentially always ready to poll
@@ -94,30 +462,183 @@ impl<'a> GrpcService<BoxBody> for Client<'a> {
     }
 
     fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
-        let ws_clone = self.ws.clone();
-        (async { call(ws_clone, request).await }).boxed_local()
+        let connection = self.connection.clone();
+        let options = self.options.clone();
+        (async move {
+            connection.metrics.call_started();
+            call(&connection, &options, request).await.map_err(|e| {
+                connection.metrics.call_failed();
+                Status::from(e)
+            })
+        })
+        .boxed_local()
     }
 }
 
-async fn call(
-    ws: WebSocketConnector,
+async fn call<T: Transport>(
+    connection: &Connection<T>,
+    options: &Options,
     mut request: Request<BoxBody>,
 ) -> Result<Response<BoxBody>, WebTonicError> {
+    let headers = request.headers_mut();
+    for (key, value) in options.metadata.iter() {
+        if !headers.contains_key(key) {
+            headers.insert(key, value.clone());
+        }
+    }
+
+    for interceptor in options.request_interceptors.iter() {
+        request = match interceptor.intercept(request).await {
+            Ok(request) => request,
+            Err(status) => return Ok(status.to_http()),
+        };
+    }
+
+    // A timeout set on the call takes precedence over the default timeout
+    let headers = request.headers_mut();
+    let timeout = match (grpc_timeout(headers), options.timeout) {
+        (Some(timeout), _) => Some(timeout),
+        (None, Some(timeout)) => {
+            headers.insert("grpc-timeout", format_grpc_timeout(timeout));
+            Some(timeout)
+        }
+        (None, None) => None,
+    };
+
+    let path = request.uri().path().to_string();
+    let retry = options
+        .retry
+        .as_ref()
+        .filter(|policy| policy.applies_to(&path));
+
     // Parse request into bytes
-    let request = webtonic_proto::http_request_to_call(&mut request).await;
+    let request = match webtonic_proto::http_request_to_call(&mut request).await {
+        Ok(request) => request.with_credits(STREAM_CREDITS),
+        Err(status) => return Ok(status.to_http()),
+    };
     let mut msg = BytesMut::new();
     request
         .encode(&mut msg)
-        .map_err(|_| WebTonicError::EncodingError)?;
+        .map_err(WebTonicError::EncodingError)?;
+    check_message_size(msg.len(), options)?;
 
     // Make the request
-    let msg = ws.send(&msg.into()).await?;
+    let msg = msg.into();
+    let exchange = exchange(connection, options, retry, &msg);
+    let reply = match timeout {
+        Some(timeout) => {
+            let timer = timer::sleep(timeout);
+            pin_mut!(exchange, timer);
+            match future::select(exchange, timer).await {
+                future::Either::Left((reply, _)) => reply?,
+                future::Either::Right(((), _)) => return Err(WebTonicError::Timeout(timeout)),
+            }
+        }
+        None => exchange.await?,
+    };
 
-    // Parse response
-    let reply = Reply::decode(msg).map_err(|_| WebTonicError::DecodingError)?;
+    // Replies of proxies in front of the server may lack a grpc status
+    if reply.grpc_status().is_none() {
+        match reply.http_status() {
+            Some(200) => (),
+            Some(status) => {
+                let code = webtonic_proto::http_status_to_code(status);
+                let message = format!("received HTTP status {} without a grpc-status", status);
+                return Ok(Status::new(code, message).to_http());
+            }
+            None => return Err(WebTonicError::DecodingError(None)),
+        }
+    }
+
+    if !options.response_interceptors.is_empty() {
+        let headers = MetadataMap::from_headers(reply.headers());
+        let trailers = MetadataMap::from_headers(reply.trailers());
+        for interceptor in options.response_interceptors.iter() {
+            if let Err(status) = interceptor.intercept(&headers, &trailers) {
+                return Ok(status.to_http());
+            }
+        }
+    }
     let response =
-        webtonic_proto::reply_to_http_response(reply).ok_or(WebTonicError::DecodingError)?;
+        webtonic_proto::reply_to_http_response(reply).ok_or(WebTonicError::DecodingError(None))?;
 
     // Return
     Ok(response)
 }
+
+/// Sends the encoded call and waits for its reply, retrying the call according to `retry`.
+async fn exchange<T: Transport>(
+    connection: &Connection<T>,
+    options: &Options,
+    retry: Option<&RetryPolicy>,
+    msg: &Bytes,
+) -> Result<Reply, WebTonicError> {
+    let mut attempt = 1;
+    loop {
+        let reply = connection.send(msg, options).await.and_then(|reply| {
+            check_message_size(reply.encoded_len(), options)?;
+            Ok(reply)
+        });
+
+        let policy = match retry {
+            Some(policy) => policy,
+            None => return reply,
+        };
+        let retry_call = match &reply {
+            Ok(reply) => {
+                matches!(reply.grpc_status(), Some(code) if policy.retry_code(code, attempt))
+            }
+            // A closed client is not reopened, so retrying is futile
+            Err(_) if connection.is_closed() => false,
+            Err(e) => policy.retry_error(e, attempt),
+        };
+        if !retry_call {
+            return reply;
+        }
+
+        console_log(&format!("retrying call after attempt {} failed", attempt));
+        connection.metrics.call_retried();
+        timer::sleep(policy.backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+fn check_message_size(size: usize, options: &Options) -> Result<(), WebTonicError> {
+    match options.max_message_size {
+        Some(limit) if size > limit => Err(WebTonicError::MessageTooLarge { size, limit }),
+        _ => Ok(()),
+    }
+}
+
+/// Parses the `grpc-timeout` header of a call.
+fn grpc_timeout(headers: &HeaderMap) -> Option<Duration> {
+    let timeout = headers.get("grpc-timeout")?.to_str().ok()?;
+    if timeout.len() < 2 {
+        return None;
+    }
+
+    let (value, unit) = timeout.split_at(timeout.len() - 1);
+    let value: u64 = value.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(value * 60 * 60),
+        "M" => Duration::from_secs(value * 60),
+        "S" => Duration::from_secs(value),
+        "m" => Duration::from_millis(value),
+        "u" => Duration::from_micros(value),
+        "n" => Duration::from_nanos(value),
+        _ => return None,
+    })
+}
+
+/// Formats a timeout as a `grpc-timeout` header, which allows at most 8 digits.
+fn format_grpc_timeout(timeout: Duration) -> HeaderValue {
+    const MAX: u128 = 99_999_999;
+
+    let millis = timeout.as_millis();
+    let value = if millis <= MAX {
+        format!("{}m", millis)
+    } else {
+        format!("{}S", timeout.as_secs().min(MAX as u64))
+    };
+    HeaderValue::from_str(&value).expect("grpc-timeout is a valid header value")
+}
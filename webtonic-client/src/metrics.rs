@@ -0,0 +1,56 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The counters of a connection, which are shared by all clones of a [`Client`](crate::Client).
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    calls: AtomicU64,
+    failed_calls: AtomicU64,
+    retries: AtomicU64,
+    reconnects: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn call_started(&self) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn call_failed(&self) {
+        self.failed_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn call_retried(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn reconnected(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> ClientMetrics {
+        ClientMetrics {
+            calls: self.calls.load(Ordering::Relaxed),
+            failed_calls: self.failed_calls.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A snapshot of the counters of a [`Client`](crate::Client), as returned by
+/// [`Client::metrics`](crate::Client::metrics).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientMetrics {
+    /// The number of calls made, not counting retries.
+    pub calls: u64,
+
+    /// The number of calls, that failed in the transport after all attempts.
+    ///
+    /// Calls answered by the server with an error status are not counted.
+    pub failed_calls: u64,
+
+    /// The number of retries made by the [`RetryPolicy`](crate::RetryPolicy).
+    pub retries: u64,
+
+    /// The number of times, the connection was reestablished.
+    pub reconnects: u64,
+}
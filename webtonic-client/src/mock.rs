@@ -0,0 +1,314 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use futures::{future::LocalBoxFuture, FutureExt};
+use http::{header::HeaderMap, request::Request, response::Response};
+use http_body::Body as HttpBody;
+use prost::Message;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    watch,
+};
+use tonic::{body::BoxBody, Status};
+use webtonic_proto::{Call, WebTonicError};
+
+use crate::{connection::Open, state::ConnectionState, transport::Transport};
+
+/// The events, the [`MockServer`](MockServer) injects into the [`MockTransport`](MockTransport).
+#[derive(Debug)]
+enum MockEvent {
+    Frame(Bytes),
+    Error(WebTonicError),
+    Close { code: u16, reason: String },
+}
+
+/// A [`Transport`](Transport), that is scripted by a [`MockServer`](MockServer).
+///
+/// Allows testing code, that uses a [`Client`](crate::Client), without a running server.
+/// The test asserts the calls made by the client and answers them through the
+/// [`MockServer`](MockServer).
+///
+/// # Example
+/// ```
+/// let (transport, mut server) = MockTransport::new();
+/// let mut client = greeter_client::GreeterClient::new(Client::with_transport(transport));
+///
+/// let call = client.say_hello(HelloRequest { name: "WebTonic".into() });
+/// let script = async {
+///     let request = server.next_call().await.unwrap();
+///     assert_eq!(request.uri().path(), "/helloworld.Greeter/SayHello");
+///
+///     server
+///         .reply(&HelloReply { message: "Hello WebTonic!".into() })
+///         .await;
+/// };
+///
+/// let (response, ()) = futures::join!(call, script);
+/// assert_eq!(response.unwrap().into_inner().message, "Hello WebTonic!");
+/// ```
+#[derive(Debug)]
+pub struct MockTransport {
+    calls: UnboundedSender<Bytes>,
+    events: UnboundedReceiver<MockEvent>,
+    open: Arc<AtomicBool>,
+}
+
+/// The scripted server side of a [`MockTransport`](MockTransport).
+#[derive(Debug)]
+pub struct MockServer {
+    calls: UnboundedReceiver<Bytes>,
+    events: UnboundedSender<MockEvent>,
+    open: Arc<AtomicBool>,
+}
+
+impl MockTransport {
+    /// Creates an open [`MockTransport`](MockTransport) and the [`MockServer`](MockServer)
+    /// scripting it.
+    pub fn new() -> (MockTransport, MockServer) {
+        let (calls_tx, calls_rx) = unbounded_channel();
+        let (events_tx, events_rx) = unbounded_channel();
+        let open = Arc::new(AtomicBool::new(true));
+
+        let transport = MockTransport {
+            calls: calls_tx,
+            events: events_rx,
+            open: open.clone(),
+        };
+        let server = MockServer {
+            calls: calls_rx,
+            events: events_tx,
+            open,
+        };
+        (transport, server)
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&mut self, frame: Bytes) -> Result<(), WebTonicError> {
+        self.calls.send(frame).map_err(|_| mock_server_dropped())
+    }
+
+    fn receive(&mut self) -> LocalBoxFuture<'_, Result<Bytes, WebTonicError>> {
+        async move {
+            match self.events.recv().await {
+                Some(MockEvent::Frame(frame)) => Ok(frame),
+                Some(MockEvent::Error(e)) => Err(e),
+                Some(MockEvent::Close { code, reason }) => Err(WebTonicError::ConnectionClosed {
+                    code: Some(code),
+                    reason,
+                }),
+                None => Err(mock_server_dropped()),
+            }
+        }
+        .boxed_local()
+    }
+
+    fn close(&mut self, _code: u16, _reason: &str) {
+        self.open.store(false, Ordering::Release);
+    }
+
+    fn is_open(&self) -> bool {
+        self.open.load(Ordering::Acquire)
+    }
+}
+
+/// Scripted endpoints, between which a [`Client`](crate::Client) fails over, like between the
+/// endpoints of [`Endpoint::from_uris`](crate::Endpoint::from_uris).
+///
+/// Every endpoint accepts a single connection, which is scripted by its
+/// [`MockServer`](MockServer).
+/// An endpoint refuses the connection, once its server was dropped or
+/// [disconnected](MockServer::disconnect).
+/// The client connects lazily, on its first call, to the first endpoint, that accepts.
+/// Once that connection is lost, it fails over to the next one.
+///
+/// # Example
+/// ```
+/// let (endpoints, mut servers) = MockEndpoints::new(2);
+/// let client = Client::with_mock_endpoints(endpoints);
+///
+/// // The first endpoint is down
+/// drop(servers.remove(0));
+/// ```
+#[derive(Debug)]
+pub struct MockEndpoints {
+    transports: Mutex<Vec<Option<MockTransport>>>,
+    next: AtomicUsize,
+}
+
+impl MockEndpoints {
+    /// Creates `count` endpoints and the [`MockServers`](MockServer) scripting them, in the
+    /// order, in which the client connects to them.
+    pub fn new(count: usize) -> (MockEndpoints, Vec<MockServer>) {
+        let (transports, servers) = (0..count)
+            .map(|_| {
+                let (transport, server) = MockTransport::new();
+                (Some(transport), server)
+            })
+            .unzip();
+
+        let endpoints = MockEndpoints {
+            transports: Mutex::new(transports),
+            next: AtomicUsize::new(0),
+        };
+        (endpoints, servers)
+    }
+}
+
+impl Open<MockTransport> for MockEndpoints {
+    fn open<'a>(
+        &'a self,
+        _state: &'a Arc<watch::Sender<ConnectionState>>,
+    ) -> LocalBoxFuture<'a, Result<MockTransport, WebTonicError>> {
+        let mut transports = self.transports.lock().unwrap_or_else(|e| e.into_inner());
+        let start = self.next.load(Ordering::Acquire);
+
+        let len = transports.len();
+        for endpoint in (0..len).map(|i| (start + i) % len) {
+            if let Some(transport) = transports[endpoint].take() {
+                if transport.is_open() {
+                    self.next.store(endpoint + 1, Ordering::Release);
+                    return futures::future::ready(Ok(transport)).boxed_local();
+                }
+            }
+        }
+
+        let error = WebTonicError::ConnectionError("all mock endpoints refused".to_string());
+        futures::future::ready(Err(error)).boxed_local()
+    }
+
+    fn endpoints(&self) -> usize {
+        self.transports
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+
+    fn lost(&self) {}
+}
+
+impl MockServer {
+    /// Waits for the next call of the client.
+    ///
+    /// # Returns
+    /// - The request of the call, whose body is the encoded protobuf message, with the gRPC
+    ///   framing removed.
+    /// - `None`, if the client was dropped or the call could not be decoded.
+    pub async fn next_call(&mut self) -> Option<Request<Bytes>> {
+        let frame = self.calls.recv().await?;
+        let call = Call::decode(frame).ok()?;
+        let (parts, mut body) = webtonic_proto::call_to_http_request(call)?.into_parts();
+
+        let mut data = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            data.put(chunk.ok()?);
+        }
+
+        // Skip the compression flag and the length prefix of the gRPC message
+        if data.len() >= 5 {
+            data.advance(5);
+        }
+        Some(Request::from_parts(parts, data.freeze()))
+    }
+
+    /// Answers the next call successfully with `message`.
+    pub async fn reply<M: Message>(&self, message: &M) {
+        let mut data = BytesMut::with_capacity(5 + message.encoded_len());
+        data.put_u8(0);
+        data.put_u32(message.encoded_len() as u32);
+        message
+            .encode(&mut data)
+            .expect("buffer has sufficient capacity");
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+
+        let response = Response::builder()
+            .header("content-type", "application/grpc")
+            .body(BoxBody::new(MockBody {
+                data: Some(data.freeze()),
+                trailers: Some(trailers),
+            }))
+            .unwrap();
+        self.reply_response(response).await
+    }
+
+    /// Answers the next call with an error `status`.
+    pub async fn reply_status(&self, status: Status) {
+        self.reply_response(status.to_http()).await
+    }
+
+    /// Answers the next call with an arbitrary HTTP `response`.
+    pub async fn reply_response(&self, mut response: Response<BoxBody>) {
+        let reply = webtonic_proto::http_response_to_reply(&mut response).await;
+        let mut frame = BytesMut::new();
+        reply
+            .encode(&mut frame)
+            .expect("failed to encode the reply");
+        self.reply_frame(frame.freeze())
+    }
+
+    /// Answers the next call with a raw `frame`, e.g. to test malformed replies.
+    pub fn reply_frame(&self, frame: Bytes) {
+        let _ = self.events.send(MockEvent::Frame(frame));
+    }
+
+    /// Fails the next call with `error`.
+    pub fn fail(&self, error: WebTonicError) {
+        let _ = self.events.send(MockEvent::Error(error));
+    }
+
+    /// Closes the connection, as if the server closed the websocket with `code` and `reason`.
+    ///
+    /// The pending call fails, as well as the following ones, unless the client reconnects.
+    pub fn disconnect(&self, code: u16, reason: &str) {
+        self.open.store(false, Ordering::Release);
+        let _ = self.events.send(MockEvent::Close {
+            code,
+            reason: reason.to_string(),
+        });
+    }
+}
+
+fn mock_server_dropped() -> WebTonicError {
+    WebTonicError::ConnectionClosed {
+        code: None,
+        reason: "mock server was dropped".to_string(),
+    }
+}
+
+/// The body of a successful reply of the [`MockServer`](MockServer).
+struct MockBody {
+    data: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl HttpBody for MockBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.data.take().map(Ok))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+}
+
+// The transport is closed, once its server is gone
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.open.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,130 @@
+use core::time::Duration;
+
+/// Configures, how a [`Client`](crate::Client) reestablishes a lost connection.
+///
+/// The delay before the `n`-th attempt is `initial_backoff * multiplier^n`, capped at
+/// `max_backoff`, and randomized by up to `jitter` in either direction.
+/// Randomizing the delays prevents all clients from reconnecting at the same time,
+/// after a server restarted.
+///
+/// # Example
+/// ```
+/// let client = Client::connect("ws://localhost:8080")
+///     .await
+///     .unwrap()
+///     .reconnect(
+///         ReconnectPolicy::new()
+///             .initial_backoff(Duration::from_millis(500))
+///             .max_attempts(10),
+///     );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) multiplier: f64,
+    pub(crate) jitter: f64,
+    pub(crate) max_attempts: Option<u32>,
+    pub(crate) queue_calls: bool,
+}
+
+impl ReconnectPolicy {
+    /// Creates a [`ReconnectPolicy`](ReconnectPolicy) with the default configuration.
+    ///
+    /// The first attempt is made immediately, the following ones after 100ms, 200ms, 400ms, ...
+    /// up to 30s, with a jitter of 20%.
+    /// The client keeps trying forever and queues the calls in the meantime.
+    pub fn new() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+            queue_calls: true,
+        }
+    }
+
+    /// Set the delay after the first failed attempt.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the maximum delay between two attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set the factor, by which the delay grows after every failed attempt.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Set the fraction of the delay, by which it is randomized, e.g. `0.2` for ±20%.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Give up after `attempts` failed attempts.
+    ///
+    /// The call, that triggered the reconnection, and all queued calls then fail with
+    /// `UNAVAILABLE` (see
+    /// [`WebTonicError::ConnectionError`](webtonic_proto::WebTonicError::ConnectionError)).
+    /// The next call starts over.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Decide, what happens to calls, that are made while the client is reconnecting.
+    ///
+    /// If `true` (the default), the calls wait until the connection is reestablished.
+    /// If `false`, they fail immediately with `UNAVAILABLE` (see
+    /// [`WebTonicError::ConnectionClosed`](webtonic_proto::WebTonicError::ConnectionClosed)).
+    pub fn queue_calls(mut self, queue: bool) -> Self {
+        self.queue_calls = queue;
+        self
+    }
+
+    /// Returns the delay before the attempt following `failed` failed attempts.
+    pub(crate) fn backoff(&self, failed: u32) -> Duration {
+        if failed == 0 {
+            return Duration::ZERO;
+        }
+
+        exponential_backoff(
+            self.initial_backoff,
+            self.max_backoff,
+            self.multiplier,
+            self.jitter,
+            failed,
+        )
+    }
+}
+
+/// Returns `initial * multiplier^(failed - 1)`, capped at `max` and randomized by `jitter`.
+pub(crate) fn exponential_backoff(
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    jitter: f64,
+    failed: u32,
+) -> Duration {
+    let exponent = failed.saturating_sub(1).min(i32::MAX as u32) as i32;
+    let backoff = initial.as_secs_f64() * multiplier.powi(exponent);
+    let backoff = backoff.min(max.as_secs_f64());
+
+    // Random factor in the range of [1 - jitter, 1 + jitter]
+    let jitter = 1.0 + jitter * (2.0 * js_sys::Math::random() - 1.0);
+    Duration::from_secs_f64(backoff * jitter)
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,213 @@
+use core::time::Duration;
+use prost::{DecodeError, Message};
+use prost_types::{method_options::IdempotencyLevel, FileDescriptorSet};
+use std::collections::HashSet;
+use tonic::Code;
+use webtonic_proto::WebTonicError;
+
+use crate::reconnect::exponential_backoff;
+
+/// The methods, a [`RetryPolicy`](RetryPolicy) applies to.
+#[derive(Debug, Clone, PartialEq)]
+enum Methods {
+    All,
+    Only(HashSet<String>),
+}
+
+/// Configures, which calls a [`Client`](crate::Client) repeats after they failed.
+///
+/// Only calls, that are safe to repeat, should be retried.
+/// Therefore, the policy applies to no method by default.
+/// The methods can either be listed [explicitly](RetryPolicy::methods), or taken from the
+/// `idempotency_level` options of a [file descriptor set](RetryPolicy::idempotent_methods).
+///
+/// A call is retried, if it failed with one of the [retryable codes](RetryPolicy::retry_on),
+/// or if the connection failed or was lost while the call was in flight.
+/// Combined with a [`ReconnectPolicy`](crate::ReconnectPolicy), calls interrupted by a
+/// connection loss are repeated transparently on the new connection.
+///
+/// # Example
+/// ```
+/// let client = Client::connect("ws://localhost:8080")
+///     .await
+///     .unwrap()
+///     .reconnect(ReconnectPolicy::new())
+///     .retry(
+///         RetryPolicy::new()
+///             .max_attempts(5)
+///             .methods(vec!["/helloworld.Greeter/SayHello"]),
+///     );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    codes: Vec<Code>,
+    methods: Methods,
+}
+
+impl RetryPolicy {
+    /// Creates a [`RetryPolicy`](RetryPolicy) with the default configuration.
+    ///
+    /// A call is made at most 3 times, retrying after 50ms and 100ms, with a jitter of 20%.
+    /// Only calls, that failed with `UNAVAILABLE` or because of the connection, are retried.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.2,
+            codes: vec![Code::Unavailable],
+            methods: Methods::Only(HashSet::new()),
+        }
+    }
+
+    /// Set the maximum number of attempts of a call, including the first one.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the maximum delay between two attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set the factor, by which the delay grows after every failed attempt.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Set the fraction of the delay, by which it is randomized, e.g. `0.2` for ±20%.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the status codes, that cause a call to be retried.
+    ///
+    /// Calls, that fail because of the connection, are always retried.
+    pub fn retry_on<I>(mut self, codes: I) -> Self
+    where
+        I: IntoIterator<Item = Code>,
+    {
+        self.codes = codes.into_iter().collect();
+        self
+    }
+
+    /// Retry the calls of the listed methods.
+    ///
+    /// # Arguments
+    /// - `methods`: the paths of the methods, e.g. `/helloworld.Greeter/SayHello`
+    pub fn methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let methods = methods.into_iter().map(Into::into);
+        match &mut self.methods {
+            Methods::All => (),
+            Methods::Only(listed) => listed.extend(methods),
+        }
+        self
+    }
+
+    /// Retry the calls of all methods.
+    ///
+    /// **Note**: Only use this, if all methods called through the client are idempotent.
+    pub fn all_methods(mut self) -> Self {
+        self.methods = Methods::All;
+        self
+    }
+
+    /// Retry the calls of all methods, that are marked as `NO_SIDE_EFFECTS` or `IDEMPOTENT`
+    /// by their `idempotency_level` option.
+    ///
+    /// # Arguments
+    /// - `descriptor`: an encoded `FileDescriptorSet`, as generated by `tonic-build` with
+    ///   `file_descriptor_set_path`
+    ///
+    /// # Returns
+    /// - The [`RetryPolicy`](RetryPolicy) with the methods added.
+    /// - An error, if the descriptor could not be decoded.
+    pub fn idempotent_methods(self, descriptor: &[u8]) -> Result<Self, DecodeError> {
+        let set = FileDescriptorSet::decode(descriptor)?;
+
+        let mut methods = vec![];
+        for file in set.file.iter() {
+            let package = match file.package() {
+                "" => String::new(),
+                package => format!("{}.", package),
+            };
+
+            for service in file.service.iter() {
+                for method in service.method.iter() {
+                    let level = method
+                        .options
+                        .as_ref()
+                        .map(|options| options.idempotency_level())
+                        .unwrap_or(IdempotencyLevel::IdempotencyUnknown);
+
+                    if level != IdempotencyLevel::IdempotencyUnknown {
+                        methods.push(format!("/{}{}/{}", package, service.name(), method.name()));
+                    }
+                }
+            }
+        }
+
+        Ok(self.methods(methods))
+    }
+
+    /// Returns `true`, if the calls of the method at `path` may be retried.
+    pub(crate) fn applies_to(&self, path: &str) -> bool {
+        match &self.methods {
+            Methods::All => true,
+            Methods::Only(methods) => methods.contains(path),
+        }
+    }
+
+    /// Returns `true`, if a call, that failed with `error` in its `attempt`-th attempt,
+    /// should be retried.
+    pub(crate) fn retry_error(&self, error: &WebTonicError, attempt: u32) -> bool {
+        let retryable = matches!(
+            error,
+            WebTonicError::ConnectionError(_) | WebTonicError::ConnectionClosed { .. }
+        );
+        retryable && attempt < self.max_attempts
+    }
+
+    /// Returns `true`, if a call, that was answered with the gRPC status `code` in its
+    /// `attempt`-th attempt, should be retried.
+    pub(crate) fn retry_code(&self, code: i32, attempt: u32) -> bool {
+        self.codes.contains(&Code::from_i32(code)) && attempt < self.max_attempts
+    }
+
+    /// Returns the delay before the retry following `failed` failed attempts.
+    pub(crate) fn backoff(&self, failed: u32) -> Duration {
+        exponential_backoff(
+            self.initial_backoff,
+            self.max_backoff,
+            self.multiplier,
+            self.jitter,
+            failed,
+        )
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
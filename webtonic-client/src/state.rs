@@ -0,0 +1,93 @@
+use core::fmt;
+use futures::{
+    stream::{self, LocalBoxStream},
+    StreamExt,
+};
+use js_sys::{Object, Reflect};
+use tokio::sync::watch;
+use wasm_bindgen::JsValue;
+
+/// The state of the connection of a [`Client`](crate::Client).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The client was [created lazily](crate::Endpoint::connect_lazy) and has not made a call yet.
+    Idle,
+
+    /// The client is opening the connection for the first time.
+    Connecting,
+
+    /// The connection is open.
+    Connected,
+
+    /// The connection was lost and the client is trying to reestablish it.
+    Reconnecting {
+        /// The number of the current attempt, starting at `1`.
+        attempt: u32,
+    },
+
+    /// The connection is closed.
+    Disconnected {
+        /// The close code sent by the server or the browser, e.g. `1006` for an abnormal closure.
+        code: Option<u16>,
+
+        /// The reason of the closure, if one was given.
+        reason: String,
+    },
+}
+
+impl ConnectionState {
+    /// Returns the name of the state, e.g. `"connected"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConnectionState::Idle => "idle",
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Reconnecting { .. } => "reconnecting",
+            ConnectionState::Disconnected { .. } => "disconnected",
+        }
+    }
+
+    /// Converts the state into a JS object of the form `{ state, attempt, code, reason }`,
+    /// where only the fields of the state are set.
+    pub fn to_js(&self) -> JsValue {
+        let object = Object::new();
+        let set = |key: &str, value: JsValue| {
+            let _ = Reflect::set(&object, &JsValue::from_str(key), &value);
+        };
+
+        set("state", JsValue::from_str(self.name()));
+        match self {
+            ConnectionState::Reconnecting { attempt } => set("attempt", JsValue::from(*attempt)),
+            ConnectionState::Disconnected { code, reason } => {
+                if let Some(code) = code {
+                    set("code", JsValue::from(*code));
+                }
+                set("reason", JsValue::from_str(reason));
+            }
+            _ => (),
+        }
+
+        object.into()
+    }
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Turns the receiver of the state into a stream of its changes.
+pub(crate) fn changes(
+    mut rx: watch::Receiver<ConnectionState>,
+) -> LocalBoxStream<'static, ConnectionState> {
+    // Only report changes, that happen after the subscription
+    rx.borrow_and_update();
+
+    stream::unfold(rx, |mut rx| async move {
+        rx.changed().await.ok()?;
+        let state = rx.borrow_and_update().clone();
+        Some((state, rx))
+    })
+    .boxed_local()
+}
@@ -0,0 +1,22 @@
+use core::time::Duration;
+use js_sys::{Function, Promise};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    // Bound directly on the global object, such that it also works in web workers
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &Function, timeout: i32) -> JsValue;
+}
+
+/// Waits for `duration`, using the timers of the browser.
+pub(crate) async fn sleep(duration: Duration) {
+    let millis = duration.as_millis().min(i32::MAX as u128) as i32;
+    let promise = Promise::new(&mut |resolve, _| {
+        set_timeout(&resolve, millis);
+    });
+
+    // The promise never rejects
+    let _ = JsFuture::from(promise).await;
+}
@@ -0,0 +1,228 @@
+use bytes::{Bytes, BytesMut};
+use core::{fmt, time::Duration};
+use futures::{future, future::LocalBoxFuture, pin_mut};
+use prost::Message;
+use webtonic_proto::{Call, Reply, WebTonicError};
+
+use crate::{console_log, fetch::FetchTransport, timer, websocket::WebSocketTransport};
+
+/// The close code, with which the client closes a connection to a silent server.
+const CLOSE_SERVER_SILENT: u16 = 4000;
+
+/// A connection to a server, over which a [`Client`](crate::Client) tunnels its calls.
+///
+/// Every call is sent as a single frame, containing the encoded
+/// [`Call`](webtonic_proto::Call), and answered by the server with a single frame, containing
+/// the encoded [`Reply`](webtonic_proto::Reply).
+/// Over a websocket, the server may stream a reply in several frames, as far as the client
+/// granted credits with further [`Calls`](webtonic_proto::Call::grant).
+/// The server answers the calls in the order, in which they were sent.
+///
+/// The client makes one call at a time over a transport, so the methods are never called
+/// concurrently.
+///
+/// The crate implements the trait for the [`BrowserTransport`](BrowserTransport), which is
+/// used by default, and the [`MockTransport`](crate::MockTransport), which is used in tests.
+pub trait Transport: fmt::Debug + 'static {
+    /// Sends a frame to the server.
+    ///
+    /// # Returns
+    /// - An error, if the frame could not be sent.
+    fn send(&mut self, frame: Bytes) -> Result<(), WebTonicError>;
+
+    /// Waits for the next frame of the server.
+    ///
+    /// # Returns
+    /// - The frame, once it was received.
+    /// - An error, if the connection failed or was closed while waiting.
+    fn receive(&mut self) -> LocalBoxFuture<'_, Result<Bytes, WebTonicError>>;
+
+    /// Closes the connection.
+    ///
+    /// # Arguments
+    /// - `code`: the websocket close code to send, e.g. `1000` for a normal closure
+    /// - `reason`: a human readable reason of the closure
+    fn close(&mut self, code: u16, reason: &str);
+
+    /// Returns `true`, if the transport is open and frames can be sent over it.
+    fn is_open(&self) -> bool;
+}
+
+/// The [`Transport`](Transport), that a [`Client`](crate::Client) opens to a server.
+///
+/// This is a [`WebSocketTransport`](WebSocketTransport), unless the websocket could not be
+/// opened and the client fell back to a [`FetchTransport`](FetchTransport)
+/// (see [`Endpoint::http_fallback`](crate::Endpoint::http_fallback)).
+#[derive(Debug)]
+pub enum BrowserTransport {
+    /// The calls are tunneled through a websocket.
+    WebSocket(WebSocketTransport),
+
+    /// Every call is sent as a HTTP request.
+    Fetch(FetchTransport),
+}
+
+impl Transport for BrowserTransport {
+    fn send(&mut self, frame: Bytes) -> Result<(), WebTonicError> {
+        match self {
+            BrowserTransport::WebSocket(transport) => transport.send(frame),
+            BrowserTransport::Fetch(transport) => transport.send(frame),
+        }
+    }
+
+    fn receive(&mut self) -> LocalBoxFuture<'_, Result<Bytes, WebTonicError>> {
+        match self {
+            BrowserTransport::WebSocket(transport) => transport.receive(),
+            BrowserTransport::Fetch(transport) => transport.receive(),
+        }
+    }
+
+    fn close(&mut self, code: u16, reason: &str) {
+        match self {
+            BrowserTransport::WebSocket(transport) => transport.close(code, reason),
+            BrowserTransport::Fetch(transport) => transport.close(code, reason),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        match self {
+            BrowserTransport::WebSocket(transport) => transport.is_open(),
+            BrowserTransport::Fetch(transport) => transport.is_open(),
+        }
+    }
+}
+
+/// A [`Transport`](Transport) of a connection, which keeps track of the abandoned calls.
+#[derive(Debug)]
+pub(crate) struct Socket<T> {
+    transport: T,
+
+    /// The number of replies to calls, that were abandoned before their reply arrived.
+    /// Those replies still arrive and need to be skipped.
+    stale: usize,
+}
+
+/// Counts the reply as stale, if the call is abandoned while waiting for it.
+struct PendingReply<'a> {
+    stale: &'a mut usize,
+    done: bool,
+}
+
+impl Drop for PendingReply<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            *self.stale += 1;
+        }
+    }
+}
+
+impl<T: Transport> Socket<T> {
+    pub(crate) fn new(transport: T) -> Self {
+        Self {
+            transport,
+            stale: 0,
+        }
+    }
+
+    /// Returns `true`, if the socket is open and can be used to send calls.
+    pub(crate) fn is_open(&self) -> bool {
+        self.transport.is_open()
+    }
+
+    /// Closes the transport with `code` and `reason`.
+    pub(crate) fn close(&mut self, code: u16, reason: &str) {
+        self.transport.close(code, reason)
+    }
+
+    /// Sends a call and waits for its reply.
+    ///
+    /// A reply, that the server streams in parts, is collected, while granting a credit for
+    /// every part received.
+    pub(crate) async fn call(
+        &mut self,
+        request: &Bytes,
+        silence_timeout: Option<Duration>,
+    ) -> Result<Reply, WebTonicError> {
+        // Sending on a closed socket may silently discard the data, so we need to check first
+        if !self.is_open() {
+            return Err(WebTonicError::ConnectionClosed {
+                code: None,
+                reason: "socket is not open".to_string(),
+            });
+        }
+
+        // The server answers the calls in order, so the replies to abandoned calls come first
+        while self.stale > 0 {
+            receive_reply(&mut self.transport, silence_timeout).await?;
+            self.stale -= 1;
+        }
+
+        self.transport.send(request.clone())?;
+
+        // Now wait for the answer
+        let Socket { transport, stale } = self;
+        let mut pending = PendingReply { stale, done: false };
+        let received = receive_reply(transport, silence_timeout).await;
+        pending.done = true;
+
+        received
+    }
+}
+
+/// Receives the next reply, collecting its parts.
+async fn receive_reply<T: Transport>(
+    transport: &mut T,
+    silence_timeout: Option<Duration>,
+) -> Result<Reply, WebTonicError> {
+    let mut reply = receive_part(transport, silence_timeout).await?;
+    while reply.is_partial() {
+        // Keep the server streaming, by replacing the credit of the received part
+        let mut grant = BytesMut::new();
+        Call::grant(1)
+            .encode(&mut grant)
+            .map_err(WebTonicError::EncodingError)?;
+        transport.send(grant.freeze())?;
+
+        let next = receive_part(transport, silence_timeout).await?;
+        reply.append(next);
+    }
+
+    Ok(reply)
+}
+
+/// Receives the next frame and decodes it as a (partial) reply.
+///
+/// The transport is closed, if the server stays silent for `silence_timeout`.
+async fn receive_part<T: Transport>(
+    transport: &mut T,
+    silence_timeout: Option<Duration>,
+) -> Result<Reply, WebTonicError> {
+    let received = match silence_timeout {
+        Some(timeout) => {
+            let received = {
+                let receive = transport.receive();
+                let timer = timer::sleep(timeout);
+                pin_mut!(receive, timer);
+                match future::select(receive, timer).await {
+                    future::Either::Left((received, _)) => Some(received),
+                    future::Either::Right(((), _)) => None,
+                }
+            };
+
+            match received {
+                Some(received) => received,
+                None => {
+                    console_log("server did not answer in time, closing the connection");
+                    transport.close(CLOSE_SERVER_SILENT, "server silent");
+                    return Err(WebTonicError::ConnectionClosed {
+                        code: Some(CLOSE_SERVER_SILENT),
+                        reason: format!("server silent for {:?}", timeout),
+                    });
+                }
+            }
+        }
+        None => transport.receive().await,
+    };
+
+    Reply::decode(received?).map_err(|e| WebTonicError::DecodingError(Some(e)))
+}
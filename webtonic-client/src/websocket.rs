@@ -1,123 +1,265 @@
 use bytes::Bytes;
-use js_sys::{Promise, Uint8Array};
+use core::time::Duration;
+use futures::{future, future::LocalBoxFuture, pin_mut, FutureExt};
+use js_sys::{Array, Promise, Uint8Array};
 use std::sync::Arc;
 use tokio::sync::{
     mpsc::{unbounded_channel, UnboundedReceiver},
-    Mutex,
+    watch,
 };
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{ErrorEvent, MessageEvent, WebSocket};
+use web_sys::{CloseEvent, ErrorEvent, MessageEvent, WebSocket};
 use webtonic_proto::WebTonicError;
 
-use crate::console_log;
+use crate::{console_log, state::ConnectionState, timer, transport::Transport};
 
-#[derive(Debug, Clone)]
-pub(crate) struct WebSocketConnector {
+/// The [`Transport`](Transport) of a [`Client`](crate::Client) connected to a server,
+/// which uses a browser websocket.
+///
+/// The socket is closed, once the transport is dropped.
+#[derive(Debug)]
+pub struct WebSocketTransport {
     ws: WebSocket,
-    rx: Arc<Mutex<UnboundedReceiver<WsMessage>>>,
+    rx: UnboundedReceiver<WsMessage>,
+
+    // The callbacks are freed together with the transport, after they were unset
+    _onerror: Closure<dyn FnMut(ErrorEvent)>,
+    _onclose: Closure<dyn FnMut(CloseEvent)>,
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
 }
 
 #[derive(Debug, Clone)]
 enum WsMessage {
     Message(JsValue),
-    Close,
+    Close { code: u16, reason: String },
     Error(JsValue),
 }
 
-impl WebSocketConnector {
-    pub(crate) async fn connect(uri: &str) -> Result<Self, WebTonicError> {
-        let ws = WebSocket::new(uri).map_err(|_| WebTonicError::InvalidUrl)?;
-        let (tx, rx) = unbounded_channel::<WsMessage>();
-        let rx = Arc::new(Mutex::new(rx));
+/// The callbacks of a socket, that is being opened.
+///
+/// Unsets the callbacks before they are freed, and closes the socket, if it was not opened.
+struct Opening {
+    ws: WebSocket,
+    opened: bool,
+    _onopen: Closure<dyn FnMut(JsValue)>,
+    _onclose: Closure<dyn FnMut(JsValue)>,
+}
+
+impl Drop for Opening {
+    fn drop(&mut self) {
+        self.ws.set_onopen(None);
+        self.ws.set_onclose(None);
+        if !self.opened {
+            let _ = self.ws.close();
+        }
+    }
+}
+
+impl WebSocketTransport {
+    /// Opens a websocket to `uri`, offering the subprotocols `protocols`.
+    ///
+    /// Once the socket is open, its closure is reported to `state`.
+    pub(crate) async fn connect(
+        uri: &str,
+        protocols: &[String],
+        connect_timeout: Option<Duration>,
+        state: Arc<watch::Sender<ConnectionState>>,
+    ) -> Result<Self, WebTonicError> {
+        let ws = match protocols {
+            [] => WebSocket::new(uri),
+            protocols => {
+                let protocols: Array = protocols.iter().map(|p| JsValue::from_str(p)).collect();
+                WebSocket::new_with_str_sequence(uri, &protocols)
+            }
+        }
+        .map_err(|e| WebTonicError::InvalidUrl(js_error_message(&e)))?;
 
         // NOTE: We can only process ArrayBuffers at the moment
         ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
-        let connect_promise = Promise::new(&mut |resolve, _| {
-            let ws_clone = ws.clone();
+        let mut opening = None;
+        let connect_promise = Promise::new(&mut |resolve, reject| {
             // Connect callback
             let onopen_callback = Closure::wrap(Box::new(move |_| {
-                // Unset onopen
-                ws_clone.set_onopen(None);
-
-                resolve.call0(&JsValue::NULL).unwrap();
+                let _ = resolve.call0(&JsValue::NULL);
             }) as Box<dyn FnMut(JsValue)>);
             ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-            onopen_callback.forget();
+
+            // A failed connection attempt is closed without ever being opened.
+            // This callback is replaced by the close callback below, once the socket is open.
+            let onclose_callback = Closure::wrap(Box::new(move |e: JsValue| {
+                let _ = reject.call1(&JsValue::NULL, &e);
+            }) as Box<dyn FnMut(JsValue)>);
+            ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+
+            opening = Some(Opening {
+                ws: ws.clone(),
+                opened: false,
+                _onopen: onopen_callback,
+                _onclose: onclose_callback,
+            });
         });
+        // Aborts the attempt, if the connection fails, times out or is abandoned
+        let mut opening = opening.expect("promise executor runs synchronously");
+
+        let connected = JsFuture::from(connect_promise);
+        let connected = match connect_timeout {
+            Some(timeout) => {
+                let timer = timer::sleep(timeout);
+                pin_mut!(timer);
+                match future::select(connected, timer).await {
+                    future::Either::Left((connected, _)) => connected,
+                    future::Either::Right(((), _)) => {
+                        return Err(WebTonicError::ConnectionError(format!(
+                            "not connected after {:?}",
+                            timeout
+                        )));
+                    }
+                }
+            }
+            None => connected.await,
+        };
+        connected.map_err(|e| {
+            let message = match e.dyn_ref::<CloseEvent>() {
+                Some(e) => format!("closed with code {} {:?}", e.code(), e.reason()),
+                None => js_error_message(&e),
+            };
+            WebTonicError::ConnectionError(message)
+        })?;
+        opening.opened = true;
+        drop(opening);
+
+        // The callbacks ignore a dropped receiver, since they may still fire while the
+        // transport is being dropped
+        let (tx, rx) = unbounded_channel::<WsMessage>();
 
         // Error callback
+        // The browser closes the socket after an error, but the close event may come later
         let tx_clone = tx.clone();
-        let onerror_callback = Closure::wrap(Box::new(move |e: ErrorEvent| {
-            tx_clone
-                .send(WsMessage::Error(JsValue::from(e)))
-                .expect("recevied error on closed socket");
+        let state_clone = state.clone();
+        let onerror = Closure::wrap(Box::new(move |e: ErrorEvent| {
+            let _ = state_clone.send(ConnectionState::Disconnected {
+                code: None,
+                reason: error_event_message(&e),
+            });
+            let _ = tx_clone.send(WsMessage::Error(JsValue::from(e)));
         }) as Box<dyn FnMut(ErrorEvent)>);
-        ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
-        onerror_callback.forget();
+        ws.set_onerror(Some(onerror.as_ref().unchecked_ref()));
 
         // Close callback
         let tx_clone = tx.clone();
-        let onclose_callback = Closure::wrap(Box::new(move |_| {
-            // TODO: Instead of send close just close the rx?
-            tx_clone.send(WsMessage::Close).unwrap();
-        }) as Box<dyn FnMut(JsValue)>);
-        ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
-        onclose_callback.forget();
+        let onclose = Closure::wrap(Box::new(move |e: CloseEvent| {
+            let (code, reason) = (e.code(), e.reason());
+            let _ = state.send(ConnectionState::Disconnected {
+                code: Some(code),
+                reason: reason.clone(),
+            });
+            let _ = tx_clone.send(WsMessage::Close { code, reason });
+        }) as Box<dyn FnMut(CloseEvent)>);
+        ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
 
         // Message Callback
-        let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
-            tx.send(WsMessage::Message(JsValue::from(e.data())))
-                .expect("received message on closed connection");
+        let onmessage = Closure::wrap(Box::new(move |e: MessageEvent| {
+            let _ = tx.send(WsMessage::Message(e.data()));
         }) as Box<dyn FnMut(MessageEvent)>);
-        ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-        onmessage_callback.forget();
+        ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
 
-        JsFuture::from(connect_promise)
-            .await
-            .map_err(|_| WebTonicError::ConnectionError)?;
-        Ok(Self { ws, rx })
+        Ok(Self {
+            ws,
+            rx,
+            _onerror: onerror,
+            _onclose: onclose,
+            _onmessage: onmessage,
+        })
     }
+}
 
-    pub(crate) async fn send(&self, request: &Bytes) -> Result<Bytes, WebTonicError> {
-        // Acquire rx, so we have exclusive access to the socket
-        let mut guard = self.rx.lock().await;
+impl Transport for WebSocketTransport {
+    fn send(&mut self, frame: Bytes) -> Result<(), WebTonicError> {
+        self.ws.send_with_u8_array(&frame).map_err(|e| {
+            console_log(&format!("Failed to send request {:?}", e));
+            WebTonicError::ConnectionError(js_error_message(&e))
+        })
+    }
 
-        // Send the actual data to the server
-        match self.ws.send_with_u8_array(request) {
-            Ok(_) => (),
-            Err(e) => {
-                console_log(&format!("Failed to send request {:?}", e));
-                return Err(WebTonicError::ConnectionError);
+    fn receive(&mut self) -> LocalBoxFuture<'_, Result<Bytes, WebTonicError>> {
+        async move {
+            match self.rx.recv().await {
+                Some(WsMessage::Message(msg)) => {
+                    let array = Uint8Array::new(&msg);
+                    Ok(Bytes::from(array.to_vec()))
+                }
+                Some(WsMessage::Error(e)) => {
+                    console_log(&format!("error while waiting for message {:?}", e));
+                    Err(socket_error(&e))
+                }
+                Some(WsMessage::Close { code, reason }) => {
+                    console_log(&format!("connection closed with {} {:?}", code, reason));
+                    Err(WebTonicError::ConnectionClosed {
+                        code: Some(code),
+                        reason,
+                    })
+                }
+                None => Err(WebTonicError::ConnectionClosed {
+                    code: None,
+                    reason: "socket was dropped".to_string(),
+                }),
             }
         }
+        .boxed_local()
+    }
 
-        // Now wait for the answer
-        let buf = match guard.recv().await {
-            Some(WsMessage::Message(msg)) => msg,
-            Some(WsMessage::Error(e)) => {
-                console_log(&format!("error while waiting for message {:?}", e));
-                return Err(WebTonicError::ConnectionError);
-            }
-            None | Some(WsMessage::Close) => {
-                return Err(WebTonicError::ConnectionClosed);
-            }
-        };
-
-        // Parse answer
-        let array = Uint8Array::new(&buf);
-        let body = Bytes::from(array.to_vec());
+    fn close(&mut self, code: u16, reason: &str) {
+        // The browser rejects reserved codes and reasons longer than 123 bytes
+        if self.ws.close_with_code_and_reason(code, reason).is_err() {
+            console_log(&format!(
+                "invalid close code {} or reason, closing normally",
+                code
+            ));
+            let _ = self.ws.close();
+        }
+    }
 
-        Ok(body)
+    fn is_open(&self) -> bool {
+        self.ws.ready_state() == WebSocket::OPEN
     }
 }
 
-// Unset all message handler once the Connector gets dropped
-impl Drop for WebSocketConnector {
+// Unset all message handler once the transport gets dropped, before the callbacks are freed
+impl Drop for WebSocketTransport {
     fn drop(&mut self) {
         self.ws.set_onclose(None);
         self.ws.set_onmessage(None);
         self.ws.set_onerror(None);
+
+        if self.is_open() || self.ws.ready_state() == WebSocket::CONNECTING {
+            let _ = self.ws.close_with_code_and_reason(1000, "client dropped");
+        }
+    }
+}
+
+/// Extracts a readable message from a JS error.
+pub(crate) fn js_error_message(e: &JsValue) -> String {
+    match e.dyn_ref::<js_sys::Error>() {
+        Some(e) => String::from(e.message()),
+        None => format!("{:?}", e),
+    }
+}
+
+/// The error of a call, that was interrupted by an error event of the socket.
+fn socket_error(e: &JsValue) -> WebTonicError {
+    let message = match e.dyn_ref::<ErrorEvent>() {
+        Some(e) => error_event_message(e),
+        None => "websocket error".to_string(),
+    };
+    WebTonicError::ConnectionError(message)
+}
+
+/// The message of an error of the websocket, browsers usually leave it empty.
+fn error_event_message(e: &ErrorEvent) -> String {
+    match e.message() {
+        message if message.is_empty() => "websocket error".to_string(),
+        message => message,
     }
 }
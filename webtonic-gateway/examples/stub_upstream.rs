@@ -0,0 +1,35 @@
+//! A stub upstream to try the gateway locally.
+//!
+//! It serves the `grpc.health.v1.Health` service on `127.0.0.1:50051`, which
+//! `gateway.example.toml` forwards to:
+//!
+//! ```bash
+//! cargo run -p webtonic-gateway --example stub_upstream
+//! cargo run -p webtonic-gateway -- webtonic-gateway/gateway.example.toml
+//! ```
+
+use std::{error::Error, net::SocketAddr};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let addr = "127.0.0.1:50051".parse()?;
+    println!("stub upstream listening on {}", addr);
+    serve(addr).await
+}
+
+/// Serves the health service, reporting every service as serving.
+///
+/// This is also used by the end to end tests of the gateway.
+pub async fn serve(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let (mut reporter, health_service) = tonic_health::server::health_reporter();
+    reporter
+        .set_service_status("", tonic_health::ServingStatus::Serving)
+        .await;
+
+    tonic::transport::Server::builder()
+        .add_service(health_service)
+        .serve(addr)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use serde::Deserialize;
+use std::{collections::HashMap, error::Error, fs, net::SocketAddr, path::Path, path::PathBuf};
+
+/// The configuration of the gateway.
+///
+/// The configuration is read from a TOML file, or from a YAML file, if the file name ends
+/// in `.yaml` or `.yml`.
+/// See `gateway.example.toml` for a documented example.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The address, on which the gateway accepts websocket connections.
+    pub listen: SocketAddr,
+
+    /// The log filter, in the syntax of `RUST_LOG`, e.g. `info` or `webtonic_server=debug`.
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+
+    /// The certificate and key, if the gateway should serve `wss://`.
+    pub tls: Option<Tls>,
+
+    /// The origins, from which browsers may connect.
+    ///
+    /// Connections from any origin are allowed, if this is not set.
+    pub allowed_origins: Option<Vec<String>>,
+
+    /// Whether calls are also accepted over plain HTTP, for clients that can not open a
+    /// websocket.
+    #[serde(default)]
+    pub http_fallback: bool,
+
+    /// Whether the gateway runs behind a reverse proxy, whose `x-forwarded-for` header is
+    /// passed on to the upstreams.
+    ///
+    /// Otherwise, the upstreams only see the address of the client, that connected to the
+    /// gateway.
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+
+    /// The address of the upstream gRPC server, by the fully qualified name of the service,
+    /// e.g. `helloworld.Greeter = "http://127.0.0.1:50051"`.
+    pub upstreams: HashMap<String, String>,
+
+    /// The resource limits of the gateway.
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+/// The TLS configuration of the gateway.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    /// The path of the PEM encoded certificate chain.
+    pub cert: PathBuf,
+
+    /// The path of the PEM encoded private key.
+    pub key: PathBuf,
+}
+
+/// The resource limits of the gateway.
+///
+/// Limits, that are not set, are not enforced.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// The maximum number of open connections.
+    pub max_connections: Option<usize>,
+
+    /// The maximum number of open connections from a single IP address.
+    pub max_connections_per_ip: Option<usize>,
+
+    /// The rate of calls, that a single connection may make.
+    pub call_rate: Option<CallRate>,
+
+    /// The maximum number of headers of a call.
+    pub max_header_count: Option<usize>,
+
+    /// The maximum size of the headers of a call in bytes.
+    pub max_header_bytes: Option<usize>,
+
+    /// The number of replies, that are buffered for a client, that is not reading.
+    /// Must be greater than zero.
+    pub send_buffer: Option<usize>,
+
+    /// What happens to a client, once its send buffer is full.
+    pub overflow_policy: Option<OverflowPolicy>,
+
+    /// The number of seconds, after which a client, that stopped reading, is disconnected.
+    pub stall_timeout_secs: Option<u64>,
+}
+
+/// What happens to a client, that does not read its replies fast enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Stop reading calls from the client, until it has caught up.
+    Block,
+
+    /// Close the connection.
+    Disconnect,
+}
+
+/// A token bucket rate limit.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CallRate {
+    /// The sustained number of calls per second.
+    pub rate: u32,
+
+    /// The number of calls, that can be made at once.
+    pub burst: u32,
+}
+
+impl Config {
+    /// Reads the configuration from a file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+        let yaml = matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("yaml") | Some("yml")
+        );
+        Self::parse(&content, yaml)
+    }
+
+    /// Parses the configuration from TOML, or from YAML, if `yaml` is set.
+    fn parse(content: &str, yaml: bool) -> Result<Self, Box<dyn Error>> {
+        let config: Self = match yaml {
+            true => serde_yaml::from_str(content)?,
+            false => toml::from_str(content)?,
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects values, that the server can not be configured with.
+    fn validate(&self) -> Result<(), String> {
+        if self.limits.send_buffer == Some(0) {
+            return Err("limits.send_buffer must be greater than zero".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_config() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("gateway.example.toml");
+        let config = Config::load(&path).unwrap();
+
+        assert_eq!(config.listen, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(
+            config.upstreams["grpc.health.v1.Health"],
+            "http://127.0.0.1:50051"
+        );
+        assert_eq!(config.limits.send_buffer, Some(32));
+        assert_eq!(config.limits.overflow_policy, Some(OverflowPolicy::Block));
+        assert_eq!(config.limits.stall_timeout_secs, Some(30));
+        assert!(config.tls.is_none());
+    }
+
+    #[test]
+    fn yaml_config() {
+        let config = Config::parse(
+            "listen: 127.0.0.1:8080\nupstreams:\n  helloworld.Greeter: http://127.0.0.1:50051\n",
+            true,
+        )
+        .unwrap();
+        assert_eq!(config.log_level, "info");
+        assert_eq!(config.upstreams.len(), 1);
+    }
+
+    #[test]
+    fn zero_send_buffer() {
+        let error = Config::parse(
+            "listen = \"127.0.0.1:8080\"\n[upstreams]\n[limits]\nsend_buffer = 0\n",
+            false,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("send_buffer"));
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let config = Config::parse(
+            "listen = \"127.0.0.1:8080\"\n[upstreams]\n[limits]\noverflow_policy = \"disconnect\"\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            config.limits.overflow_policy,
+            Some(OverflowPolicy::Disconnect)
+        );
+
+        let config = Config::parse(
+            "listen = \"127.0.0.1:8080\"\n[upstreams]\n[limits]\noverflow_policy = \"drop\"\n",
+            false,
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn unknown_field() {
+        let config = Config::parse(
+            "listen = \"127.0.0.1:8080\"\nlisten_port = 8080\n[upstreams]\n",
+            false,
+        );
+        assert!(config.is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! A standalone gateway of the [`WebTonic`](https://github.com/Sawchord/webtonic) project.
+//!
+//! The gateway accepts websocket connections from browsers and forwards the tunneled calls to
+//! existing gRPC servers, as configured in a TOML or YAML file:
+//!
+//! ```bash
+//! webtonic-gateway gateway.toml
+//! ```
+
+mod config;
+
+use std::{error::Error, path::PathBuf, time::Duration};
+use tonic::transport::Endpoint;
+use webtonic_server::{OriginPolicy, OverflowPolicy, ProxyService, Server};
+
+use crate::config::Config;
+
+const DEFAULT_CONFIG: &str = "gateway.toml";
+
+/// The send buffer of the server, if only the overflow policy is configured.
+const DEFAULT_SEND_BUFFER: usize = 32;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let path = PathBuf::from(
+        std::env::args()
+            .nth(1)
+            .unwrap_or_else(|| DEFAULT_CONFIG.to_string()),
+    );
+    let config = Config::load(&path)?;
+
+    pretty_env_logger::formatted_builder()
+        .parse_filters(&config.log_level)
+        .init();
+
+    let mut server = Server::builder();
+
+    if let Some(origins) = &config.allowed_origins {
+        server = server.origin_policy(OriginPolicy::allow_list(origins));
+    }
+    if config.http_fallback {
+        server = server.http_fallback();
+    }
+    if let Some(tls) = &config.tls {
+        server = server.tls(&tls.cert, &tls.key);
+    }
+
+    let limits = &config.limits;
+    if let Some(max) = limits.max_connections {
+        server = server.max_connections(max);
+    }
+    if let Some(max) = limits.max_connections_per_ip {
+        server = server.max_connections_per_ip(max);
+    }
+    if let Some(rate) = limits.call_rate {
+        server = server.call_rate(rate.rate, rate.burst);
+    }
+    if limits.max_header_count.is_some() || limits.max_header_bytes.is_some() {
+        server = server.max_headers(
+            limits.max_header_count.unwrap_or(usize::MAX),
+            limits.max_header_bytes.unwrap_or(usize::MAX),
+        );
+    }
+    if limits.send_buffer.is_some() || limits.overflow_policy.is_some() {
+        let policy = match limits.overflow_policy {
+            Some(config::OverflowPolicy::Disconnect) => OverflowPolicy::Disconnect,
+            Some(config::OverflowPolicy::Block) | None => OverflowPolicy::Block,
+        };
+        server = server.send_buffer(limits.send_buffer.unwrap_or(DEFAULT_SEND_BUFFER), policy);
+    }
+    if let Some(secs) = limits.stall_timeout_secs {
+        server = server.stall_timeout(Duration::from_secs(secs));
+    }
+
+    let mut router = server.router();
+    for (service, uri) in &config.upstreams {
+        let endpoint = Endpoint::from_shared(uri.clone())
+            .map_err(|e| format!("invalid upstream {:?} of {}: {}", uri, service, e))?;
+        log::info!("forwarding {} to {}", service, uri);
+        let mut proxy = ProxyService::lazy(endpoint);
+        if config.trust_forwarded_for {
+            proxy = proxy.trust_forwarded_for();
+        }
+        router = router.add_named_service(service.clone(), proxy);
+    }
+
+    log::info!("gateway listening on {}", config.listen);
+    router.serve(config.listen).await;
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! End to end tests of the gateway, forwarding calls to the stub upstream of the examples.
+
+#[allow(dead_code)]
+#[path = "../examples/stub_upstream.rs"]
+mod stub_upstream;
+
+use bytes::Bytes;
+use http_body::Body as _;
+use prost::Message;
+use std::{
+    fs,
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    process::{Child, Command},
+    time::Duration,
+};
+use tonic::body::BoxBody;
+use webtonic_proto::{Reply, HTTP_CONTENT_TYPE};
+
+/// A running gateway, that is killed at the end of the test.
+struct Gateway {
+    process: Child,
+    config: PathBuf,
+}
+
+impl Gateway {
+    /// Starts the gateway on `listen`, forwarding the health service to `upstream`.
+    fn start(listen: SocketAddr, upstream: SocketAddr) -> Self {
+        let config =
+            std::env::temp_dir().join(format!("webtonic-gateway-test-{}.toml", listen.port()));
+        fs::write(
+            &config,
+            format!(
+                "listen = \"{}\"\n\
+                 log_level = \"warn\"\n\
+                 http_fallback = true\n\
+                 [upstreams]\n\
+                 \"grpc.health.v1.Health\" = \"http://{}\"\n",
+                listen, upstream
+            ),
+        )
+        .unwrap();
+
+        let process = Command::new(env!("CARGO_BIN_EXE_webtonic-gateway"))
+            .arg(&config)
+            .spawn()
+            .unwrap();
+        Self { process, config }
+    }
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_file(&self.config);
+    }
+}
+
+/// Returns an address on localhost, that is currently unused.
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+/// Encodes a call to `path`, with an empty `HealthCheckRequest`, which asks for the overall
+/// health.
+async fn health_check(path: &str) -> Bytes {
+    // An empty `HealthCheckRequest` in a gRPC frame
+    let frame = Bytes::from_static(&[0, 0, 0, 0, 0]);
+    let body = http_body::Full::new(frame).map_err(|never| match never {});
+    let mut request = http::Request::builder()
+        .method("POST")
+        .uri(path)
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(BoxBody::new(body))
+        .unwrap();
+
+    let call = webtonic_proto::http_request_to_call(&mut request)
+        .await
+        .unwrap();
+    call.encode_to_vec().into()
+}
+
+/// Sends a call over HTTP, retrying while the gateway is still starting.
+async fn send(gateway: SocketAddr, call: Bytes) -> Reply {
+    let client = hyper::Client::new();
+    for _ in 0..100 {
+        let request = http::Request::builder()
+            .method("POST")
+            .uri(format!("http://{}/", gateway))
+            .header("content-type", HTTP_CONTENT_TYPE)
+            .body(hyper::Body::from(call.clone()))
+            .unwrap();
+
+        match client.request(request).await {
+            Ok(response) => {
+                assert_eq!(response.status(), http::StatusCode::OK);
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                return Reply::decode(body).unwrap();
+            }
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+    panic!("gateway did not start");
+}
+
+#[tokio::test]
+async fn forwards_to_upstream() {
+    let upstream = free_addr();
+    tokio::spawn(async move {
+        let _ = stub_upstream::serve(upstream).await;
+    });
+
+    let listen = free_addr();
+    let _gateway = Gateway::start(listen, upstream);
+
+    let reply = send(listen, health_check("/grpc.health.v1.Health/Check").await).await;
+    assert_eq!(reply.grpc_status(), Some(tonic::Code::Ok as i32));
+
+    // A `HealthCheckResponse` with the status `SERVING`
+    let mut response = webtonic_proto::reply_to_http_response(reply).unwrap();
+    let data = response.body_mut().data().await.unwrap().unwrap();
+    assert_eq!(data.as_ref(), &[0, 0, 0, 0, 2, 8, 1]);
+}
+
+#[tokio::test]
+async fn unknown_service() {
+    let listen = free_addr();
+    let _gateway = Gateway::start(listen, free_addr());
+
+    let reply = send(listen, health_check("/grpc.unknown.Service/Check").await).await;
+    assert_eq!(reply.grpc_status(), Some(tonic::Code::Unimplemented as i32));
+}
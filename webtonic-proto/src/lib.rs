@@ -14,9 +14,11 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use bytes::{Buf, Bytes};
 use core::{
+    convert::TryFrom,
     fmt,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 use http::{
     header::{HeaderMap, HeaderName, HeaderValue},
@@ -26,37 +28,137 @@ use http::{
     version::Version,
 };
 use http_body::Body as HttpBody;
-use prost::{Enumeration, Message};
+use prost::{DecodeError, EncodeError, Enumeration, Message};
 use std::error::Error;
-use tonic::body::BoxBody;
+use tonic::{body::BoxBody, Code, Status};
+
+/// The content type of [`Calls`](Call) and [`Replies`](Reply), that are sent over plain HTTP
+/// instead of a websocket.
+pub const HTTP_CONTENT_TYPE: &str = "application/x-webtonic+proto";
 
 /// The error type of `WebTonic`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum WebTonicError {
     /// The url entered is not a valid url.
-    InvalidUrl,
+    ///
+    /// Contains the message of the browser.
+    InvalidUrl(String),
 
     /// The endpoint could not connect to the supplied url.
-    ConnectionError,
+    ///
+    /// Contains a description of the failure.
+    ConnectionError(String),
 
     /// Error while encoding a `Request` or `Response`.
     ///
     /// This is likely a bug or implementation shortcomming of `WebTonic`.
-    EncodingError,
+    EncodingError(EncodeError),
 
     /// Failed to decode a received packet.
     ///
     /// Likely the other side sent a malformed packet.
-    DecodingError,
+    /// Contains the error of the decoder, if the packet was not valid protobuf.
+    DecodingError(Option<DecodeError>),
 
     /// The connection was closed unexpectedly.
-    ConnectionClosed,
+    ConnectionClosed {
+        /// The close code of the websocket, if it is known, e.g. `1006` for an abnormal closure.
+        code: Option<u16>,
+
+        /// The reason of the closure.
+        reason: String,
+    },
+
+    /// The call did not finish within its timeout.
+    Timeout(Duration),
+
+    /// The call or its reply exceeded the maximum message size.
+    MessageTooLarge {
+        /// The size of the encoded message.
+        size: usize,
+
+        /// The maximum allowed size.
+        limit: usize,
+    },
+}
+
+impl Error for WebTonicError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WebTonicError::EncodingError(e) => Some(e),
+            WebTonicError::DecodingError(Some(e)) => Some(e),
+            _ => None,
+        }
+    }
 }
 
-impl Error for WebTonicError {}
 impl fmt::Display for WebTonicError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            WebTonicError::InvalidUrl(message) => write!(f, "invalid url: {}", message),
+            WebTonicError::ConnectionError(message) => write!(f, "failed to connect: {}", message),
+            WebTonicError::EncodingError(e) => write!(f, "failed to encode the call: {}", e),
+            WebTonicError::DecodingError(Some(e)) => {
+                write!(f, "failed to decode the reply: {}", e)
+            }
+            WebTonicError::DecodingError(None) => f.write_str("failed to decode the reply"),
+            WebTonicError::ConnectionClosed {
+                code: Some(code),
+                reason,
+            } => write!(f, "connection closed with code {}: {}", code, reason),
+            WebTonicError::ConnectionClosed { code: None, reason } => {
+                write!(f, "connection closed: {}", reason)
+            }
+            WebTonicError::Timeout(timeout) => write!(f, "call timed out after {:?}", timeout),
+            WebTonicError::MessageTooLarge { size, limit } => write!(
+                f,
+                "message of {} bytes exceeds the limit of {} bytes",
+                size, limit
+            ),
+        }
+    }
+}
+
+impl From<WebTonicError> for Status {
+    /// Maps the error to the closest gRPC [`Code`](Code):
+    /// - `UNAVAILABLE`, if the connection failed or was lost
+    /// - `INTERNAL`, if a message could not be encoded or decoded
+    /// - `DEADLINE_EXCEEDED`, if the call timed out
+    /// - `RESOURCE_EXHAUSTED`, if a message was too large
+    /// - `INVALID_ARGUMENT`, if the url is invalid
+    fn from(error: WebTonicError) -> Self {
+        let code = match &error {
+            WebTonicError::InvalidUrl(_) => Code::InvalidArgument,
+            WebTonicError::ConnectionError(_) | WebTonicError::ConnectionClosed { .. } => {
+                Code::Unavailable
+            }
+            WebTonicError::EncodingError(_) | WebTonicError::DecodingError(_) => Code::Internal,
+            WebTonicError::Timeout(_) => Code::DeadlineExceeded,
+            WebTonicError::MessageTooLarge { .. } => Code::ResourceExhausted,
+        };
+        Status::new(code, error.to_string())
+    }
+}
+
+/// Maps the HTTP status of a reply without a `grpc-status` to a gRPC [`Code`](Code).
+///
+/// This follows the
+/// [mapping of the gRPC project](https://github.com/grpc/grpc/blob/master/doc/http-grpc-status-mapping.md).
+/// Such replies are usually produced by proxies or load balancers in front of the server.
+///
+/// # Arguments
+/// - `status`: the HTTP status code of the reply
+///
+/// # Returns
+/// - the corresponding gRPC [`Code`](Code), `UNKNOWN` if there is none
+pub fn http_status_to_code(status: u16) -> Code {
+    match status {
+        400 => Code::Internal,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::Unimplemented,
+        429 | 502 | 503 | 504 => Code::Unavailable,
+        _ => Code::Unknown,
     }
 }
 
@@ -115,6 +217,62 @@ pub struct Call {
     request: Option<Request>,
     #[prost(message, tag = "2")]
     body: Option<Body>,
+    /// The number of partial replies, the client accepts before it grants more.
+    #[prost(uint32, tag = "3")]
+    credits: u32,
+}
+
+impl Call {
+    /// Creates a [`Call`](Call), that grants `credits` to the reply, that is currently being
+    /// streamed, instead of making a call.
+    pub fn grant(credits: u32) -> Self {
+        Self {
+            request: None,
+            body: None,
+            credits,
+        }
+    }
+
+    /// Returns `true`, if the [`Call`](Call) only grants credits (see [`Call::grant`](Call::grant)).
+    pub fn is_grant(&self) -> bool {
+        self.request.is_none() && self.credits > 0
+    }
+
+    /// Creates an empty [`Call`](Call), that the server answers with an `OK` status, without
+    /// calling a service.
+    ///
+    /// Clients use it to check, whether a server accepts calls over HTTP.
+    pub fn probe() -> Self {
+        Self {
+            request: None,
+            body: None,
+            credits: 0,
+        }
+    }
+
+    /// Returns `true`, if the [`Call`](Call) is a [probe](Call::probe).
+    pub fn is_probe(&self) -> bool {
+        self.request.is_none() && self.credits == 0
+    }
+
+    /// Returns the number of credits of the [`Call`](Call).
+    ///
+    /// A call with credits may be answered with a stream of partial replies
+    /// (see [`Reply::is_partial`](Reply::is_partial)), one for each credit.
+    /// A call without credits is answered with a single reply.
+    pub fn credits(&self) -> u32 {
+        self.credits
+    }
+
+    /// Accept a stream of partial replies to the [`Call`](Call).
+    ///
+    /// # Arguments
+    /// - `credits`: the number of partial replies, that may be sent before the client grants
+    ///   more
+    pub fn with_credits(mut self, credits: u32) -> Self {
+        self.credits = credits;
+        self
+    }
 }
 
 /// A protobuf encodable representation of a [`Response`](https://docs.rs/http/0.2.1/http/response/struct.Response.html).
@@ -124,6 +282,79 @@ pub struct Reply {
     response: Option<Response>,
     #[prost(message, tag = "2")]
     body: Option<Body>,
+    /// Whether more replies to the same call follow.
+    #[prost(bool, tag = "3")]
+    partial: bool,
+}
+
+impl Reply {
+    /// Returns `true`, if the [`Reply`](Reply) only carries a part of the body and more
+    /// replies to the same call follow.
+    ///
+    /// The response is sent with the first part, the trailers with the last one.
+    pub fn is_partial(&self) -> bool {
+        self.partial
+    }
+
+    /// Creates the last part of a streamed reply, whose trailers carry `status`.
+    ///
+    /// This ends a stream, whose response was already sent with a previous part.
+    pub fn status_trailers(status: Status) -> Self {
+        Self {
+            response: None,
+            body: Some(status_body(status)),
+            partial: false,
+        }
+    }
+
+    /// Appends the `next` part of a streamed reply.
+    pub fn append(&mut self, next: Reply) {
+        if self.response.is_none() {
+            self.response = next.response;
+        }
+        if let Some(next) = next.body {
+            let body = self.body.get_or_insert_with(Body::empty);
+            body.body.extend_from_slice(&next.body);
+            body.trailers.extend(next.trailers);
+        }
+        self.partial = next.partial;
+    }
+
+    /// Returns the gRPC status code of the [`Reply`](Reply), if it contains one.
+    ///
+    /// The status is looked up in the headers first, where it is placed in "trailers-only"
+    /// replies, and in the trailers otherwise.
+    pub fn grpc_status(&self) -> Option<i32> {
+        let headers = self.response.iter().flat_map(|response| &response.headers);
+        let trailers = self.body.iter().flat_map(|body| &body.trailers);
+
+        headers
+            .chain(trailers)
+            .find(|header| header.name == "grpc-status")
+            .and_then(|header| header.value.parse().ok())
+    }
+
+    /// Returns the HTTP status code of the [`Reply`](Reply), if it contains one.
+    pub fn http_status(&self) -> Option<u16> {
+        let status = self.response.as_ref()?.status;
+        u16::try_from(status).ok()
+    }
+
+    /// Returns the headers of the [`Reply`](Reply).
+    ///
+    /// Headers, that are not valid HTTP headers, are skipped.
+    pub fn headers(&self) -> HeaderMap {
+        let headers = self.response.iter().flat_map(|response| &response.headers);
+        headers_to_http_headers(headers)
+    }
+
+    /// Returns the trailers of the [`Reply`](Reply).
+    ///
+    /// Trailers, that are not valid HTTP headers, are skipped.
+    pub fn trailers(&self) -> HeaderMap {
+        let trailers = self.body.iter().flat_map(|body| &body.trailers);
+        headers_to_http_headers(trailers)
+    }
 }
 
 /// Parses a [`Request`](https://docs.rs/http/0.2.1/http/request/struct.Request.html) into [`Call`](Call).
@@ -133,15 +364,22 @@ pub struct Reply {
 ///
 /// # Returns
 /// - the protobuf encodable [`Call`](Call) object
-pub async fn http_request_to_call(request: &mut HttpRequest<BoxBody>) -> Call {
-    let body = http_body_to_body(request).await;
+/// - the error of the body, if it could not be read completely
+pub async fn http_request_to_call(request: &mut HttpRequest<BoxBody>) -> Result<Call, Status> {
+    let body = http_body_to_body(request)
+        .await
+        .map_err(|(_, status)| status)?;
     let request = Some(Request {
         uri: format!("{:?}", request.uri()),
         method: http_method_to_method(request.method()) as i32,
         headers: http_headers_to_headers(request.headers()),
     });
 
-    Call { request, body }
+    Ok(Call {
+        request,
+        body,
+        credits: 0,
+    })
 }
 
 /// Parses a [`Call`](Call) into a [`Request`](https://docs.rs/http/0.2.1/http/request/struct.Request.html).
@@ -155,22 +393,17 @@ pub async fn http_request_to_call(request: &mut HttpRequest<BoxBody>) -> Call {
 pub fn call_to_http_request(call: Call) -> Option<HttpRequest<BoxBody>> {
     use http::request::Builder;
 
-    let request = match call.request {
-        Some(request) => request,
-        None => return None,
-    };
+    let request = call.request?;
 
     let mut builder = Builder::new()
         .version(Version::HTTP_2)
-        .method(method_to_http_method(
-            Method::from_i32(request.method).unwrap(),
-        ))
+        .method(method_to_http_method(Method::from_i32(request.method)?))
         .uri(request.uri);
 
     for header in request.headers {
         builder = builder.header(
-            HeaderName::from_bytes(header.name.as_bytes()).unwrap(),
-            HeaderValue::from_str(header.value.as_str()).unwrap(),
+            HeaderName::from_bytes(header.name.as_bytes()).ok()?,
+            HeaderValue::from_str(header.value.as_str()).ok()?,
         )
     }
 
@@ -190,15 +423,97 @@ pub fn call_to_http_request(call: Call) -> Option<HttpRequest<BoxBody>> {
 ///
 /// # Returns
 /// - the protobuf encodable [`Reply`](Reply) object
+///
+/// If the body fails, e.g. a stream ending with an error, the reply contains the data read so
+/// far and the error as its trailers, like a gRPC server would send it.
 pub async fn http_response_to_reply(response: &mut HttpResponse<BoxBody>) -> Reply {
-    let body = http_body_to_body(response).await;
+    let body = match http_body_to_body(response).await {
+        Ok(body) => body,
+        Err((body, status)) => Some(Body {
+            body,
+            ..status_body(status)
+        }),
+    };
 
     let response = Some(Response {
         status: response.status().as_u16() as u32,
         headers: http_headers_to_headers(response.headers()),
     });
 
-    Reply { response, body }
+    Reply {
+        response,
+        body,
+        partial: false,
+    }
+}
+
+/// Splits a [`Response`](https://docs.rs/http/0.2.1/http/response/struct.Response.html)
+/// into partial [`Replies`](Reply), one for every frame of its body.
+///
+/// This allows sending the frames of a streaming response as they are produced, instead of
+/// collecting the whole body like [`http_response_to_reply`](http_response_to_reply).
+#[derive(Debug)]
+pub struct ReplyParts<'a> {
+    response: &'a mut HttpResponse<BoxBody>,
+    head_sent: bool,
+}
+
+impl<'a> ReplyParts<'a> {
+    /// Creates the [`ReplyParts`](ReplyParts) of `response`.
+    pub fn new(response: &'a mut HttpResponse<BoxBody>) -> Self {
+        Self {
+            response,
+            head_sent: false,
+        }
+    }
+
+    /// Waits for the next part of the reply.
+    ///
+    /// # Returns
+    /// - A [partial](Reply::is_partial) [`Reply`](Reply) with the next frame of the body.
+    /// - The last [`Reply`](Reply), containing the trailers, once the body ended.
+    ///   If the body failed, the trailers contain the error.
+    pub async fn next(&mut self) -> Reply {
+        let (body, partial) = match self.response.body_mut().data().await {
+            Some(Ok(mut frame)) => {
+                let body = Body {
+                    body: frame.copy_to_bytes(frame.remaining()).to_vec(),
+                    trailers: vec![],
+                };
+                (Some(body), true)
+            }
+            Some(Err(status)) => (Some(status_body(status)), false),
+            None => match self.response.body_mut().trailers().await {
+                Ok(trailers) => {
+                    let body = trailers.map(|trailers| Body {
+                        body: vec![],
+                        trailers: http_headers_to_headers(&trailers),
+                    });
+                    (body, false)
+                }
+                Err(status) => (Some(status_body(status)), false),
+            },
+        };
+
+        Reply {
+            response: self.head(),
+            body,
+            partial,
+        }
+    }
+
+    /// Returns the response, if it was not sent with a previous part.
+    fn head(&mut self) -> Option<Response> {
+        if self.head_sent {
+            return None;
+        }
+        self.head_sent = true;
+
+        Some(Response {
+            status: self.response.status().as_u16() as u32,
+            headers: http_headers_to_headers(self.response.headers()),
+        })
+    }
 }
 
 /// Parse a [`Reply`](Reply) into a [`Response`](https://docs.rs/http/0.2.1/http/response/struct.Response.html).
@@ -209,7 +524,7 @@ pub async fn http_response_to_reply(response: &mut HttpResponse<BoxBody>) -> Rep
 /// # Returns
 /// - `Some(response)`, if parsing the
 /// [`Response`](https://docs.rs/http/0.2.1/http/response/struct.Response.html) succeded
-/// - `None`, if parsing failed
+/// - `None`, if parsing failed, e.g. because of an invalid header
 pub fn reply_to_http_response(reply: Reply) -> Option<HttpResponse<BoxBody>> {
     use http::response::Builder;
 
@@ -224,8 +539,8 @@ pub fn reply_to_http_response(reply: Reply) -> Option<HttpResponse<BoxBody>> {
 
     for header in response.headers {
         builder = builder.header(
-            HeaderName::from_bytes(header.name.as_bytes()).unwrap(),
-            HeaderValue::from_str(header.value.as_str()).unwrap(),
+            HeaderName::from_bytes(header.name.as_bytes()).ok()?,
+            HeaderValue::from_str(header.value.as_str()).ok()?,
         )
     }
     builder
@@ -236,12 +551,29 @@ pub fn reply_to_http_response(reply: Reply) -> Option<HttpResponse<BoxBody>> {
         .ok()
 }
 
+fn headers_to_http_headers<'h>(headers: impl Iterator<Item = &'h Header>) -> HeaderMap {
+    headers
+        .filter_map(|header| {
+            Some((
+                HeaderName::from_bytes(header.name.as_bytes()).ok()?,
+                HeaderValue::from_str(header.value.as_str()).ok()?,
+            ))
+        })
+        .fold(HeaderMap::new(), |mut headers, (name, value)| {
+            headers.append(name, value);
+            headers
+        })
+}
+
+/// Converts the headers, skipping the ones, whose value is not visible ASCII.
 fn http_headers_to_headers(headers: &HeaderMap) -> Vec<Header> {
     headers
         .iter()
-        .map(|(header_name, header_value)| Header {
-            name: header_name.as_str().to_string(),
-            value: header_value.to_str().unwrap().to_string(),
+        .filter_map(|(header_name, header_value)| {
+            Some(Header {
+                name: header_name.as_str().to_string(),
+                value: header_value.to_str().ok()?.to_string(),
+            })
         })
         .collect()
 }
@@ -261,22 +593,36 @@ fn http_method_to_method(method: &HttpMethod) -> Method {
     }
 }
 
-async fn http_body_to_body<B>(body: &mut B) -> Option<Body>
+/// Reads all frames and the trailers of a body.
+///
+/// # Returns
+/// - The [`Body`](Body), if there was any data or trailers.
+/// - The data read so far and the error, if the body failed.
+async fn http_body_to_body<B>(body: &mut B) -> Result<Option<Body>, (Vec<u8>, Status)>
 where
-    B: HttpBody + Unpin,
+    B: HttpBody<Error = Status> + Unpin,
 {
+    // Streaming bodies may consist of multiple frames, all of which need to be
+    // collected before the trailers become available
+    let mut data: Option<Vec<u8>> = None;
+    while let Some(frame) = body.data().await {
+        match frame {
+            Ok(mut frame) => data
+                .get_or_insert_with(Vec::new)
+                .extend_from_slice(&frame.copy_to_bytes(frame.remaining())),
+            Err(status) => return Err((data.unwrap_or_default(), status)),
+        }
+    }
+
     let trailers = match body.trailers().await {
         Ok(Some(trailers)) => Some(http_headers_to_headers(&trailers)),
         Ok(None) => None,
-        Err(_) => None,
+        Err(status) => return Err((data.unwrap_or_default(), status)),
     };
 
-    let body = match body.data().await {
-        Some(Ok(mut body)) => Some(body.copy_to_bytes(body.remaining()).to_vec()),
-        _ => None,
-    };
+    let body = data;
 
-    match (body, trailers) {
+    Ok(match (body, trailers) {
         (None, None) => None,
         (Some(body), None) => Some(Body {
             body,
@@ -287,6 +633,18 @@ where
             trailers,
         }),
         (Some(body), Some(trailers)) => Some(Body { body, trailers }),
+    })
+}
+
+/// Returns an empty body, whose trailers carry `status`.
+fn status_body(status: Status) -> Body {
+    let response = status.to_http();
+    let mut trailers = http_headers_to_headers(response.headers());
+    trailers.retain(|header| header.name.starts_with("grpc-"));
+
+    Body {
+        body: vec![],
+        trailers,
     }
 }
 
@@ -326,15 +684,18 @@ impl HttpBody for Body {
         _cx: &mut Context,
     ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
         if !self.trailers.is_empty() {
+            let trailers = std::mem::take(&mut self.get_mut().trailers);
             let mut res = HeaderMap::new();
 
-            for trailer in &self.trailers {
-                res.append(
-                    HeaderName::from_bytes(trailer.name.as_bytes()).unwrap(),
-                    HeaderValue::from_str(trailer.value.as_str()).unwrap(),
-                );
+            for trailer in trailers {
+                let name = HeaderName::from_bytes(trailer.name.as_bytes()).map_err(|_| {
+                    Status::internal(format!("invalid trailer name {:?}", trailer.name))
+                })?;
+                let value = HeaderValue::from_str(trailer.value.as_str()).map_err(|_| {
+                    Status::internal(format!("invalid value of trailer {:?}", trailer.name))
+                })?;
+                res.append(name, value);
             }
-            self.get_mut().trailers.clear();
 
             Poll::Ready(Ok(Some(res)))
         } else {
@@ -351,3 +712,155 @@ impl Body {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use futures::executor::block_on;
+
+    /// A body, that yields some frames and then fails.
+    struct FailingBody(Vec<Result<Bytes, Status>>);
+
+    impl HttpBody for FailingBody {
+        type Data = Bytes;
+        type Error = Status;
+
+        fn poll_data(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Bytes, Status>>> {
+            Poll::Ready(match self.0.is_empty() {
+                true => None,
+                false => Some(self.0.remove(0)),
+            })
+        }
+
+        fn poll_trailers(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<Option<HeaderMap>, Status>> {
+            Poll::Ready(Ok(None))
+        }
+    }
+
+    fn response(frames: Vec<Result<Bytes, Status>>) -> HttpResponse<BoxBody> {
+        HttpResponse::new(BoxBody::new(FailingBody(frames)))
+    }
+
+    #[test]
+    fn failed_stream_ends_with_status() {
+        let mut response = response(vec![
+            Ok(Bytes::from_static(b"first")),
+            Err(Status::data_loss("stream broke")),
+            Ok(Bytes::from_static(b"never read")),
+        ]);
+        let reply = block_on(http_response_to_reply(&mut response));
+
+        assert_eq!(reply.grpc_status(), Some(Code::DataLoss as i32));
+        assert_eq!(reply.body.unwrap().body, b"first");
+    }
+
+    #[test]
+    fn complete_stream() {
+        let mut response = response(vec![
+            Ok(Bytes::from_static(b"first")),
+            Ok(Bytes::from_static(b"second")),
+        ]);
+        let reply = block_on(http_response_to_reply(&mut response));
+
+        assert_eq!(reply.grpc_status(), None);
+        assert_eq!(reply.body.unwrap().body, b"firstsecond");
+    }
+
+    #[test]
+    fn failed_request_body() {
+        let mut request = HttpRequest::new(BoxBody::new(FailingBody(vec![Err(
+            Status::cancelled("gone"),
+        )])));
+        let status = block_on(http_request_to_call(&mut request)).unwrap_err();
+        assert_eq!(status.code(), Code::Cancelled);
+    }
+
+    #[test]
+    fn reply_parts() {
+        let mut response = response(vec![
+            Ok(Bytes::from_static(b"first")),
+            Ok(Bytes::from_static(b"second")),
+        ]);
+        let mut parts = ReplyParts::new(&mut response);
+
+        let mut reply = block_on(parts.next());
+        assert!(reply.is_partial());
+        assert!(reply.response.is_some());
+
+        let second = block_on(parts.next());
+        assert!(second.is_partial());
+        assert!(second.response.is_none());
+        reply.append(second);
+
+        reply.append(block_on(parts.next()));
+        assert!(!reply.is_partial());
+        assert_eq!(reply.body.unwrap().body, b"firstsecond");
+    }
+
+    #[test]
+    fn failed_reply_parts() {
+        let mut response = response(vec![
+            Ok(Bytes::from_static(b"first")),
+            Err(Status::data_loss("stream broke")),
+        ]);
+        let mut parts = ReplyParts::new(&mut response);
+
+        let mut reply = block_on(parts.next());
+        reply.append(block_on(parts.next()));
+
+        assert!(!reply.is_partial());
+        assert_eq!(reply.grpc_status(), Some(Code::DataLoss as i32));
+        assert_eq!(reply.body.unwrap().body, b"first");
+    }
+
+    #[test]
+    fn grant() {
+        let grant = Call::grant(3);
+        assert!(grant.is_grant());
+        assert_eq!(grant.credits(), 3);
+
+        let probe = Call::decode(Call::probe().encode_to_vec().as_slice()).unwrap();
+        assert!(probe.is_probe());
+        assert!(!probe.is_grant());
+        assert!(!grant.is_probe());
+    }
+
+    #[test]
+    fn invalid_reply_header() {
+        let reply = Reply {
+            response: Some(Response {
+                status: 200,
+                headers: vec![Header {
+                    name: "invalid name".to_string(),
+                    value: "value".to_string(),
+                }],
+            }),
+            body: None,
+            partial: false,
+        };
+        assert!(reply_to_http_response(reply).is_none());
+    }
+
+    #[test]
+    fn invalid_trailer() {
+        let mut body = Body {
+            body: vec![],
+            trailers: vec![Header {
+                name: "grpc-message".to_string(),
+                value: "line\nbreak".to_string(),
+            }],
+        };
+        let status = block_on(body.trailers()).unwrap_err();
+        assert_eq!(status.code(), Code::Internal);
+    }
+}
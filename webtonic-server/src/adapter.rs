@@ -0,0 +1,84 @@
+use bytes::Bytes;
+use core::task::{Context, Poll};
+use futures::future::BoxFuture;
+use http::{request::Request, response::Response};
+use http_body::Body as HttpBody;
+use std::error::Error;
+use tonic::{body::BoxBody, Status};
+use tower_service::Service;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Serves a tonic [`Router`](https://docs.rs/tonic/0.6.2/tonic/transport/server/struct.Router.html)
+/// over the websocket tunnel.
+///
+/// Tonic routers operate on `hyper` bodies, while the tunnel produces requests with a
+/// [`BoxBody`](tonic::body::BoxBody).
+/// This adapter converts between the two, such that an existing router, including all of its
+/// layers, can be reused without registering the services again.
+///
+/// # Example
+/// ```ignore
+/// let routes = tonic::transport::Server::builder()
+///     .layer(my_layer)
+///     .add_service(GreeterServer::new(greeter))
+///     .add_service(EchoServer::new(echo));
+///
+/// webtonic_server::Server::builder()
+///     .add_routes(TonicRouter::new(routes.into_service()))
+///     .serve(([127, 0, 0, 1], 8080))
+///     .await;
+/// ```
+#[derive(Debug, Clone)]
+pub struct TonicRouter<S>(S);
+
+impl<S> TonicRouter<S> {
+    /// Wraps a `hyper` based service, e.g. the result of
+    /// [`Router::into_service`](https://docs.rs/tonic/0.6.2/tonic/transport/server/struct.Router.html#method.into_service).
+    pub fn new(service: S) -> Self {
+        Self(service)
+    }
+}
+
+impl<S, B> Service<Request<BoxBody>> for TonicRouter<S>
+where
+    S: Service<Request<hyper::Body>, Response = Response<B>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response<BoxBody>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
+        // Take the service that was driven to readiness and leave a fresh clone in its place
+        let clone = self.0.clone();
+        let mut service = core::mem::replace(&mut self.0, clone);
+
+        Box::pin(async move {
+            // The body of a call is already fully buffered, so nothing is lost by collecting it
+            let (parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            let request = Request::from_parts(parts, hyper::Body::from(body));
+
+            let response = service.call(request).await.map_err(Into::into)?;
+            Ok(response.map(|body| BoxBody::new(body.map_err(to_status))))
+        })
+    }
+}
+
+pub(crate) fn to_status<E>(error: E) -> Status
+where
+    E: Into<BoxError>,
+{
+    match error.into().downcast::<Status>() {
+        Ok(status) => *status,
+        Err(error) => Status::internal(error.to_string()),
+    }
+}
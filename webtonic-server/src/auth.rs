@@ -0,0 +1,66 @@
+use core::{fmt, future::Future};
+use futures::{future::BoxFuture, FutureExt};
+use http::Extensions;
+use std::sync::Arc;
+
+use crate::ConnectionInfo;
+
+/// The type erased identity, an [`Authenticator`](Authenticator) has accepted.
+#[derive(Clone)]
+pub(crate) struct Identity(Arc<dyn Fn(&mut Extensions) + Send + Sync>);
+
+impl Identity {
+    fn new<I>(identity: I) -> Self
+    where
+        I: Clone + Send + Sync + 'static,
+    {
+        Self(Arc::new(move |extensions: &mut Extensions| {
+            extensions.insert(identity.clone());
+        }))
+    }
+
+    /// Inserts the identity into the `extensions` of a request.
+    pub(crate) fn insert_into(&self, extensions: &mut Extensions) {
+        (self.0)(extensions)
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Identity")
+    }
+}
+
+/// The upgrade time authentication callback of a [`Server`](crate::Server).
+#[derive(Clone)]
+pub(crate) struct Authenticator(
+    Arc<dyn Fn(ConnectionInfo) -> BoxFuture<'static, Option<Identity>> + Send + Sync>,
+);
+
+impl Authenticator {
+    pub(crate) fn new<F, Fut, I>(f: F) -> Self
+    where
+        F: Fn(ConnectionInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<I>> + Send + 'static,
+        I: Clone + Send + Sync + 'static,
+    {
+        Self(Arc::new(move |info| {
+            f(info).map(|identity| identity.map(Identity::new)).boxed()
+        }))
+    }
+
+    /// Runs the callback on the connection.
+    ///
+    /// # Returns
+    /// - `Some(identity)`, if the connection was accepted
+    /// - `None`, if the connection was rejected
+    pub(crate) async fn authenticate(&self, info: ConnectionInfo) -> Option<Identity> {
+        (self.0)(info).await
+    }
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Authenticator")
+    }
+}
@@ -0,0 +1,360 @@
+use bytes::BytesMut;
+use core::time::Duration;
+use futures::{Stream, StreamExt};
+use prost::Message as ProstMessage;
+use std::collections::VecDeque;
+use tokio::{
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    time,
+};
+use warp::ws::Message;
+use webtonic_proto::{Call, Reply};
+
+/// Decides, what happens to a connection, whose client does not read its replies fast enough.
+///
+/// Every connection buffers a bounded number of outgoing replies
+/// (see [`Server::send_buffer`](crate::Server::send_buffer)).
+/// Once that buffer is full, the policy applies.
+///
+/// Clients, that grant credits with their calls, receive the replies of streaming calls as
+/// one message per item, as far as they have credits left.
+/// The handler is paused, until the client grants more, so the buffer is counted in items.
+/// Other clients receive the whole reply as a single message, once the stream ended.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Stop reading calls from the connection, until the client has caught up.
+    ///
+    /// No new handlers are started on the connection while it is blocked,
+    /// which propagates the backpressure to the client.
+    /// The connection is closed, if the client does not catch up within the
+    /// [stall timeout](crate::Server::stall_timeout).
+    /// This is the default policy.
+    #[default]
+    Block,
+
+    /// Close the connection.
+    Disconnect,
+}
+
+/// The reasons, why a reply could not be queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SendError {
+    /// The buffer was full and the policy is [`Disconnect`](OverflowPolicy::Disconnect).
+    Full,
+
+    /// The websocket was closed.
+    Closed,
+
+    /// The client did not read the replies or grant credits within the stall timeout.
+    Stalled,
+
+    /// The client sent more calls, than the send buffer holds, while a reply was waiting
+    /// for credits.
+    Flooded,
+}
+
+/// The sending half of the bounded buffer of outgoing messages of a connection.
+pub(crate) struct Outbound {
+    tx: Sender<Result<Message, warp::Error>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    stall_timeout: Duration,
+    #[cfg(feature = "metrics")]
+    metrics: Option<crate::metrics::Metrics>,
+}
+
+impl Outbound {
+    /// Creates the buffer.
+    ///
+    /// A [blocked](OverflowPolicy::Block) send fails, once it waited for `stall_timeout`.
+    ///
+    /// # Returns
+    /// - The [`Outbound`](Outbound) to queue messages.
+    /// - The receiver, that needs to be forwarded to the websocket.
+    pub(crate) fn new(
+        capacity: usize,
+        policy: OverflowPolicy,
+        stall_timeout: Duration,
+    ) -> (Self, Receiver<Result<Message, warp::Error>>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let outbound = Self {
+            tx,
+            capacity,
+            policy,
+            stall_timeout,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        };
+
+        (outbound, rx)
+    }
+
+    /// Counts the times, the buffer ran full, in `metrics`.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn with_metrics(mut self, metrics: Option<crate::metrics::Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Queues a message, applying the [`OverflowPolicy`](OverflowPolicy) if the buffer is full.
+    pub(crate) async fn send(&self, msg: Message) -> Result<(), SendError> {
+        let msg = match self.tx.try_send(Ok(msg)) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Closed(_)) => return Err(SendError::Closed),
+            Err(TrySendError::Full(msg)) => msg,
+        };
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.send_buffer_full(self.policy);
+        }
+
+        match self.policy {
+            OverflowPolicy::Block => {
+                log::debug!("send buffer is full, waiting for the client to catch up");
+                time::timeout(self.stall_timeout, self.tx.send(msg))
+                    .await
+                    .map_err(|_| SendError::Stalled)?
+                    .map_err(|_| SendError::Closed)
+            }
+            OverflowPolicy::Disconnect => Err(SendError::Full),
+        }
+    }
+}
+
+/// The receiving half of a websocket.
+pub(crate) type Inbound = dyn Stream<Item = Result<Message, warp::Error>> + Send + Unpin;
+
+/// Sends the partial replies of a streaming call, as far as the client granted credits.
+pub(crate) struct ReplyStream<'a> {
+    tx: &'a Outbound,
+    rx: &'a mut Inbound,
+    backlog: &'a mut VecDeque<Message>,
+    credits: u32,
+
+    /// The number of bytes sent as partial replies.
+    pub(crate) sent: usize,
+
+    /// The error, that interrupted the stream.
+    /// The connection can not be used any more.
+    pub(crate) error: Option<SendError>,
+}
+
+impl<'a> ReplyStream<'a> {
+    /// Creates the stream of a call.
+    ///
+    /// Messages, that are received while waiting for credits, are queued in `backlog`, which
+    /// holds as many messages as the send buffer of `tx`.
+    pub(crate) fn new(
+        tx: &'a Outbound,
+        rx: &'a mut Inbound,
+        backlog: &'a mut VecDeque<Message>,
+    ) -> Self {
+        Self {
+            tx,
+            rx,
+            backlog,
+            credits: 0,
+            sent: 0,
+            error: None,
+        }
+    }
+
+    /// Sets the credits, that the client granted with its call.
+    pub(crate) fn start(&mut self, credits: u32) {
+        self.credits = credits;
+    }
+
+    /// Sends a partial reply, once the client has a credit left.
+    pub(crate) async fn send(&mut self, reply: Reply) -> Result<(), SendError> {
+        while self.credits == 0 {
+            let received = time::timeout(self.tx.stall_timeout, self.rx.next())
+                .await
+                .map_err(|_| SendError::Stalled)?;
+
+            match received {
+                Some(Ok(msg)) => self.receive(msg)?,
+                _ => return Err(SendError::Closed),
+            }
+        }
+        self.credits -= 1;
+
+        let mut msg = BytesMut::new();
+        reply.encode(&mut msg).expect("buffer grows as needed");
+        self.sent += msg.len();
+        self.tx.send(Message::binary(msg.as_ref())).await
+    }
+
+    /// Takes the credits granted by `msg` and queues all other messages.
+    ///
+    /// The backlog can not be blocked like the send buffer, since the credits arrive over the
+    /// same connection, so a full backlog fails regardless of the policy.
+    fn receive(&mut self, msg: Message) -> Result<(), SendError> {
+        if msg.is_binary() {
+            if let Ok(call) = Call::decode(msg.as_bytes()) {
+                if call.is_grant() {
+                    self.credits = self.credits.saturating_add(call.credits());
+                    return Ok(());
+                }
+            }
+        }
+
+        if msg.is_close() {
+            return Err(SendError::Closed);
+        }
+        if self.backlog.len() >= self.tx.capacity {
+            return Err(SendError::Flooded);
+        }
+
+        self.backlog.push_back(msg);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use tokio::sync::mpsc::UnboundedSender;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    type Frame = Result<Message, warp::Error>;
+
+    fn binary(byte: u8) -> Message {
+        Message::binary(vec![byte])
+    }
+
+    fn grant(credits: u32) -> Message {
+        Message::binary(Call::grant(credits).encode_to_vec())
+    }
+
+    /// Creates a reply, that is told apart by its status `code`.
+    fn part(code: i32) -> Reply {
+        Reply::status_trailers(tonic::Status::new(tonic::Code::from_i32(code), ""))
+    }
+
+    fn code(msg: Message) -> Option<i32> {
+        Reply::decode(msg.as_bytes()).unwrap().grpc_status()
+    }
+
+    async fn next(rx: &mut Receiver<Frame>) -> Message {
+        rx.recv().await.unwrap().unwrap()
+    }
+
+    /// Creates the receiving half of a websocket and the sender to feed it.
+    fn inbound() -> (UnboundedSender<Frame>, UnboundedReceiverStream<Frame>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, UnboundedReceiverStream::new(rx))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn block_on_full_buffer() {
+        let (tx, mut rx) = Outbound::new(1, OverflowPolicy::Block, 10 * SECOND);
+        tx.send(binary(1)).await.unwrap();
+
+        // The second message is sent, once the first one was read
+        let (sent, first) = tokio::join!(tx.send(binary(2)), next(&mut rx));
+        assert_eq!(sent, Ok(()));
+        assert_eq!(first, binary(1));
+        assert_eq!(next(&mut rx).await, binary(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn disconnect_on_full_buffer() {
+        let (tx, mut rx) = Outbound::new(1, OverflowPolicy::Disconnect, 10 * SECOND);
+        tx.send(binary(1)).await.unwrap();
+        assert_eq!(tx.send(binary(2)).await, Err(SendError::Full));
+
+        drop(rx.recv().await);
+        drop(rx);
+        assert_eq!(tx.send(binary(3)).await, Err(SendError::Closed));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn blocked_send_stalls() {
+        let (tx, _rx) = Outbound::new(1, OverflowPolicy::Block, 10 * SECOND);
+        tx.send(binary(1)).await.unwrap();
+
+        let started = time::Instant::now();
+        assert_eq!(tx.send(binary(2)).await, Err(SendError::Stalled));
+        assert_eq!(started.elapsed(), 10 * SECOND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stream_waits_for_credits() {
+        let (tx, mut rx) = Outbound::new(4, OverflowPolicy::Block, 10 * SECOND);
+        let (client, mut inbound) = inbound();
+        let mut backlog = VecDeque::new();
+        let mut stream = ReplyStream::new(&tx, &mut inbound, &mut backlog);
+
+        stream.start(1);
+        stream.send(part(1)).await.unwrap();
+        assert_eq!(code(next(&mut rx).await), Some(1));
+
+        // Without credits, the stream pauses until the client grants more
+        assert_eq!(stream.send(part(2)).now_or_never(), None);
+        client.send(Ok(grant(2))).unwrap();
+        stream.send(part(2)).await.unwrap();
+        stream.send(part(3)).await.unwrap();
+        assert_eq!(code(next(&mut rx).await), Some(2));
+        assert_eq!(code(next(&mut rx).await), Some(3));
+        assert_eq!(stream.send(part(4)).now_or_never(), None);
+        assert!(stream.sent > 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backlog_keeps_order() {
+        let (tx, _rx) = Outbound::new(4, OverflowPolicy::Block, 10 * SECOND);
+        let (client, mut inbound) = inbound();
+        let mut backlog = VecDeque::new();
+        let mut stream = ReplyStream::new(&tx, &mut inbound, &mut backlog);
+
+        // Calls, that are sent while waiting for credits, are processed after the stream
+        client.send(Ok(binary(1))).unwrap();
+        client.send(Ok(binary(2))).unwrap();
+        client.send(Ok(grant(1))).unwrap();
+        client.send(Ok(binary(3))).unwrap();
+        stream.send(part(1)).await.unwrap();
+
+        assert_eq!(backlog, [binary(1), binary(2)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backlog_is_bounded() {
+        let (tx, _rx) = Outbound::new(2, OverflowPolicy::Block, 10 * SECOND);
+        let (client, mut inbound) = inbound();
+        let mut backlog = VecDeque::new();
+        let mut stream = ReplyStream::new(&tx, &mut inbound, &mut backlog);
+
+        for byte in 1..=3 {
+            client.send(Ok(binary(byte))).unwrap();
+        }
+        client.send(Ok(grant(1))).unwrap();
+        assert_eq!(stream.send(part(1)).await, Err(SendError::Flooded));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stream_stalls() {
+        let (tx, _rx) = Outbound::new(4, OverflowPolicy::Block, 10 * SECOND);
+        let (_client, mut inbound) = inbound();
+        let mut backlog = VecDeque::new();
+        let mut stream = ReplyStream::new(&tx, &mut inbound, &mut backlog);
+
+        let started = time::Instant::now();
+        assert_eq!(stream.send(part(1)).await, Err(SendError::Stalled));
+        assert_eq!(started.elapsed(), 10 * SECOND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stream_closed() {
+        let (tx, _rx) = Outbound::new(4, OverflowPolicy::Block, 10 * SECOND);
+        let (client, mut inbound) = inbound();
+        let mut backlog = VecDeque::new();
+        let mut stream = ReplyStream::new(&tx, &mut inbound, &mut backlog);
+
+        client.send(Ok(Message::close())).unwrap();
+        assert_eq!(stream.send(part(1)).await, Err(SendError::Closed));
+    }
+}
@@ -0,0 +1,110 @@
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use http::{header::HeaderMap, Extensions};
+use std::{net::SocketAddr, sync::Arc};
+
+use crate::auth::Identity;
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A process wide unique identifier of a websocket connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    fn next() -> Self {
+        Self(NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the numeric value of this id.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Information about the websocket connection, over which a call was received.
+///
+/// Since all calls are tunneled through a single websocket, tonic's
+/// [`Request::remote_addr`](https://docs.rs/tonic/0.6.2/tonic/struct.Request.html#method.remote_addr)
+/// is always `None`.
+/// Instead, the [`Server`](crate::Server) attaches a [`ConnectionInfo`](ConnectionInfo) to the
+/// extensions of every request, which can be accessed from within the handlers.
+///
+/// # Example
+/// ```ignore
+/// async fn say_hello(
+///     &self,
+///     request: Request<HelloRequest>,
+/// ) -> Result<Response<HelloReply>, Status> {
+///     let info = request.extensions().get::<ConnectionInfo>().unwrap();
+///     println!("Got a request from {:?}", info.remote_addr());
+///     ...
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    id: ConnectionId,
+    remote_addr: Option<SocketAddr>,
+    headers: Arc<HeaderMap>,
+    query: Option<Arc<str>>,
+    identity: Option<Identity>,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(
+        remote_addr: Option<SocketAddr>,
+        headers: HeaderMap,
+        query: Option<String>,
+    ) -> Self {
+        Self {
+            id: ConnectionId::next(),
+            remote_addr,
+            headers: Arc::new(headers),
+            query: query.map(Arc::from),
+            identity: None,
+        }
+    }
+
+    pub(crate) fn set_identity(&mut self, identity: Identity) {
+        self.identity = Some(identity);
+    }
+
+    /// Inserts this [`ConnectionInfo`](ConnectionInfo) and the identity of the connection,
+    /// if there is one, into the `extensions` of a request.
+    pub(crate) fn insert_into(&self, extensions: &mut Extensions) {
+        if let Some(identity) = &self.identity {
+            identity.insert_into(extensions);
+        }
+        extensions.insert(self.clone());
+    }
+
+    /// Returns the [`ConnectionId`](ConnectionId) of the connection.
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /// Returns the address of the peer, if it is known.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// Returns the headers of the http request, that upgraded the connection to a websocket.
+    ///
+    /// These contain e.g. the cookies, the user agent and the origin of the browser.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the raw query string of the upgrade request, if there was one.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+}
@@ -0,0 +1,141 @@
+//! Support for the [gRPC health checking protocol](https://github.com/grpc/grpc/blob/master/doc/health-checking.md).
+//!
+//! The `grpc.health.v1.Health` service can be added to the [`Router`](crate::Router)
+//! like any other service.
+//! The same serving status can additionally be exposed as a plain HTTP endpoint
+//! (see [`Server::healthz`](crate::Server::healthz)), such that load balancers and
+//! orchestrators can probe the server without speaking the tunnel protocol.
+//!
+//! # Example
+//! ```ignore
+//! let (health, health_service) = webtonic_server::health::health_reporter();
+//! health.set_serving::<GreeterServer<MyGreeter>>().await;
+//!
+//! webtonic_server::Server::builder()
+//!     .healthz(health.clone())
+//!     .add_service(health_service)
+//!     .add_service(GreeterServer::new(greeter))
+//!     .serve(([127, 0, 0, 1], 8080))
+//!     .await;
+//! ```
+
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+use tonic::transport::NamedService;
+use tonic_health::{
+    proto::health_server::{Health, HealthServer},
+    server::HealthReporter,
+};
+use warp::http::StatusCode;
+
+pub use tonic_health::{
+    proto::GRPC_HEALTH_V1_FILE_DESCRIPTOR_SET as FILE_DESCRIPTOR_SET, ServingStatus,
+};
+
+/// Creates a [`HealthHandle`](HealthHandle) and the linked `grpc.health.v1.Health` service.
+///
+/// # Returns
+/// - The [`HealthHandle`](HealthHandle) used to update the serving status.
+/// - The `HealthServer`, which needs to be [added](crate::Router::add_service) to the router.
+pub fn health_reporter() -> (HealthHandle, HealthServer<impl Health>) {
+    let (reporter, server) = tonic_health::server::health_reporter();
+    let handle = HealthHandle {
+        reporter,
+        statuses: Arc::new(RwLock::new(HashMap::new())),
+    };
+
+    (handle, server)
+}
+
+/// A handle to update the serving status of the services.
+///
+/// Changes are reflected in both the `grpc.health.v1.Health` service and the HTTP endpoint.
+#[derive(Debug, Clone)]
+pub struct HealthHandle {
+    reporter: HealthReporter,
+    statuses: Arc<RwLock<HashMap<String, ServingStatus>>>,
+}
+
+impl HealthHandle {
+    /// Sets the status of the service `S` to [`Serving`](ServingStatus::Serving).
+    pub async fn set_serving<S>(&self)
+    where
+        S: NamedService,
+    {
+        self.set_service_status(<S as NamedService>::NAME, ServingStatus::Serving)
+            .await
+    }
+
+    /// Sets the status of the service `S` to [`NotServing`](ServingStatus::NotServing).
+    pub async fn set_not_serving<S>(&self)
+    where
+        S: NamedService,
+    {
+        self.set_service_status(<S as NamedService>::NAME, ServingStatus::NotServing)
+            .await
+    }
+
+    /// Sets the status of a service.
+    ///
+    /// # Arguments
+    /// - `service_name`: the fully qualified name of the service,
+    ///   or the empty string to set the status of the server as a whole
+    /// - `status`: the new [`ServingStatus`](ServingStatus)
+    pub async fn set_service_status<S>(&self, service_name: S, status: ServingStatus)
+    where
+        S: AsRef<str>,
+    {
+        let service_name = service_name.as_ref();
+        let mut statuses = self.statuses.write().await;
+        self.reporter
+            .clone()
+            .set_service_status(service_name, status)
+            .await;
+        statuses.insert(service_name.to_string(), status);
+    }
+
+    /// Removes the status of a service.
+    pub async fn clear_service_status(&self, service_name: &str) {
+        let mut statuses = self.statuses.write().await;
+        self.reporter
+            .clone()
+            .clear_service_status(service_name)
+            .await;
+        statuses.remove(service_name);
+    }
+
+    /// Returns the current status of a service.
+    ///
+    /// # Returns
+    /// - `Some(status)`, if the status of the service was set
+    /// - `None`, if the service is unknown
+    pub async fn service_status(&self, service_name: &str) -> Option<ServingStatus> {
+        self.statuses.read().await.get(service_name).copied()
+    }
+
+    /// Answers a request to the HTTP health endpoint.
+    ///
+    /// Without a `service` in the query, the server is healthy if all services are serving.
+    pub(crate) async fn http_status(&self, service: Option<&str>) -> (StatusCode, String) {
+        let statuses = self.statuses.read().await;
+
+        let status = match service {
+            Some(service) => match statuses.get(service) {
+                Some(status) => *status,
+                None => return (StatusCode::NOT_FOUND, "service not registered".to_string()),
+            },
+            None if statuses
+                .values()
+                .all(|status| *status == ServingStatus::Serving) =>
+            {
+                ServingStatus::Serving
+            }
+            None => ServingStatus::NotServing,
+        };
+
+        match status {
+            ServingStatus::Serving => (StatusCode::OK, status.to_string()),
+            _ => (StatusCode::SERVICE_UNAVAILABLE, status.to_string()),
+        }
+    }
+}
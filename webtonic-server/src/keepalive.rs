@@ -0,0 +1,186 @@
+use core::time::Duration;
+use futures::future;
+use tokio::time::{self, Instant};
+
+/// The timeouts of the connections of a [`Server`](crate::Server).
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Timeouts {
+    pub(crate) ping_interval: Option<Duration>,
+    pub(crate) pong_timeout: Duration,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) max_age: Option<Duration>,
+}
+
+/// The events, that [`Keepalive::next`](Keepalive::next) waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// It is time to ping the client.
+    Ping,
+
+    /// The client did not answer the last ping in time.
+    PongTimeout,
+
+    /// There were no calls for the duration of the idle timeout.
+    Idle,
+
+    /// The connection reached its maximum age.
+    MaxAge,
+}
+
+/// Tracks the liveness of a single connection.
+#[derive(Debug)]
+pub(crate) struct Keepalive {
+    timeouts: Timeouts,
+    opened: Instant,
+    last_call: Instant,
+    last_ping: Instant,
+    pong_pending: Option<Instant>,
+}
+
+impl Keepalive {
+    pub(crate) fn new(timeouts: Timeouts) -> Self {
+        let now = Instant::now();
+        Self {
+            timeouts,
+            opened: now,
+            last_call: now,
+            last_ping: now,
+            pong_pending: None,
+        }
+    }
+
+    /// Waits for the next [`Event`](Event).
+    ///
+    /// Never returns, if no timeouts are configured.
+    pub(crate) async fn next(&self) -> Event {
+        let timeouts = &self.timeouts;
+        let ping = match self.pong_pending {
+            Some(sent) => Some((sent + timeouts.pong_timeout, Event::PongTimeout)),
+            None => timeouts
+                .ping_interval
+                .map(|interval| (self.last_ping + interval, Event::Ping)),
+        };
+        let idle = timeouts
+            .idle_timeout
+            .map(|timeout| (self.last_call + timeout, Event::Idle));
+        let age = timeouts
+            .max_age
+            .map(|age| (self.opened + age, Event::MaxAge));
+
+        match ping
+            .into_iter()
+            .chain(idle)
+            .chain(age)
+            .min_by_key(|(at, _)| *at)
+        {
+            Some((at, event)) => {
+                time::sleep_until(at).await;
+                event
+            }
+            None => future::pending().await,
+        }
+    }
+
+    /// Records, that a ping was sent to the client.
+    pub(crate) fn ping_sent(&mut self) {
+        let now = Instant::now();
+        self.last_ping = now;
+        self.pong_pending = Some(now);
+    }
+
+    /// Records, that a message was received from the client.
+    ///
+    /// Any message proves, that the client is still alive, not only the pong.
+    pub(crate) fn received(&mut self) {
+        self.pong_pending = None;
+    }
+
+    /// Records, that a call was finished, which restarts the idle timeout.
+    pub(crate) fn call_finished(&mut self) {
+        self.last_call = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    /// Waits for the next event and returns it along with the time it took.
+    async fn next(keepalive: &Keepalive) -> (Event, Duration) {
+        let started = Instant::now();
+        let event = keepalive.next().await;
+        (event, started.elapsed())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_timeouts() {
+        let keepalive = Keepalive::new(Timeouts::default());
+        time::advance(Duration::from_secs(3600)).await;
+        assert_eq!(keepalive.next().now_or_never(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ping_and_pong_timeout() {
+        let mut keepalive = Keepalive::new(Timeouts {
+            ping_interval: Some(10 * SECOND),
+            pong_timeout: 2 * SECOND,
+            ..Timeouts::default()
+        });
+
+        assert_eq!(next(&keepalive).await, (Event::Ping, 10 * SECOND));
+        keepalive.ping_sent();
+        assert_eq!(next(&keepalive).await, (Event::PongTimeout, 2 * SECOND));
+
+        // An answer cancels the pong timeout and the next ping follows the interval
+        keepalive.received();
+        assert_eq!(next(&keepalive).await, (Event::Ping, 8 * SECOND));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_deadline() {
+        let mut keepalive = Keepalive::new(Timeouts {
+            idle_timeout: Some(30 * SECOND),
+            ..Timeouts::default()
+        });
+
+        time::advance(20 * SECOND).await;
+        keepalive.call_finished();
+        assert_eq!(next(&keepalive).await, (Event::Idle, 30 * SECOND));
+
+        // Messages other than calls do not restart the idle timeout
+        keepalive.call_finished();
+        time::advance(20 * SECOND).await;
+        keepalive.received();
+        assert_eq!(next(&keepalive).await, (Event::Idle, 10 * SECOND));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn max_age_deadline() {
+        let mut keepalive = Keepalive::new(Timeouts {
+            idle_timeout: Some(30 * SECOND),
+            max_age: Some(60 * SECOND),
+            ..Timeouts::default()
+        });
+
+        // Calls keep the connection from idling, but not from aging
+        for _ in 0..3 {
+            time::advance(20 * SECOND).await;
+            keepalive.call_finished();
+        }
+        assert_eq!(keepalive.next().now_or_never(), Some(Event::MaxAge));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn earliest_deadline_first() {
+        let keepalive = Keepalive::new(Timeouts {
+            ping_interval: Some(10 * SECOND),
+            pong_timeout: SECOND,
+            idle_timeout: Some(5 * SECOND),
+            max_age: Some(60 * SECOND),
+        });
+        assert_eq!(next(&keepalive).await, (Event::Idle, 5 * SECOND));
+    }
+}
@@ -5,30 +5,81 @@
 //! It is designed to mimic the
 //! [`Tonic`](https://docs.rs/tonic/0.3.1/tonic/transport/struct.Server.html) implementation.
 
+mod adapter;
+mod auth;
+mod backpressure;
+mod connection;
+#[cfg(feature = "health")]
+pub mod health;
+mod keepalive;
+mod limits;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod origin;
+mod panic;
+mod proxy;
+#[cfg(feature = "reflection")]
+pub mod reflection;
+mod router;
+#[cfg(feature = "tracing")]
+mod trace;
+
 use bytes::{Bytes, BytesMut};
 use core::{
     marker::{Send, Sync},
-    task::Context,
-    task::Poll,
+    time::Duration,
+};
+use futures::{Future, FutureExt, StreamExt};
+use http::{
+    header::{
+        HeaderMap, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+        CONTENT_TYPE, VARY,
+    },
+    request::Request,
+    response::Response,
+    StatusCode,
 };
-use futures::{future, StreamExt};
-use http::{request::Request, response::Response};
 use prost::Message as ProstMessage;
-use std::net::SocketAddr;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
-use tokio_stream::wrappers::UnboundedReceiverStream;
-use tonic::{
-    body::{empty_body, BoxBody},
-    codegen::Never,
-    transport::NamedService,
-    Status,
+#[cfg(feature = "health")]
+use std::collections::HashMap;
+#[cfg(feature = "tls")]
+use std::path::PathBuf;
+use std::{
+    collections::VecDeque, convert::Infallible, error::Error, net::SocketAddr,
+    panic::AssertUnwindSafe, sync::Arc, time::Instant,
 };
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{body::BoxBody, transport::NamedService, Status};
 use tower_service::Service;
 use warp::{
     ws::{Message, WebSocket},
-    Filter,
+    Filter, Reply,
+};
+use webtonic_proto::{Call, ReplyParts};
+
+pub use crate::adapter::TonicRouter;
+pub use crate::backpressure::OverflowPolicy;
+pub use crate::connection::{ConnectionId, ConnectionInfo};
+pub use crate::origin::OriginPolicy;
+pub use crate::proxy::ProxyService;
+pub use crate::router::{BoxService, Router, Unimplemented};
+use crate::{
+    auth::Authenticator,
+    backpressure::{Outbound, ReplyStream, SendError},
+    keepalive::{Event, Keepalive, Timeouts},
+    limits::{ConnectionPermit, Limit, Limits},
+    panic::PanicHook,
 };
-use webtonic_proto::Call;
+
+/// The default number of replies, that are buffered per connection.
+const DEFAULT_SEND_BUFFER: usize = 32;
+
+/// The default time, a connection waits for a client, that does not read its replies.
+const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The maximum size of a call made over HTTP, which matches the maximum websocket message size.
+const MAX_HTTP_CALL_SIZE: u64 = 64 << 20;
 
 /// The server endpoint of the `WebTonic` websocket bridge.
 ///
@@ -48,7 +99,23 @@ use webtonic_proto::Call;
 ///     .await;
 /// ```
 #[derive(Debug, Clone)]
-pub struct Server {}
+pub struct Server {
+    authenticator: Option<Authenticator>,
+    origin_policy: OriginPolicy,
+    panic_hook: Option<PanicHook>,
+    send_buffer: usize,
+    overflow_policy: OverflowPolicy,
+    stall_timeout: Duration,
+    timeouts: Timeouts,
+    limits: Limits,
+    http_fallback: bool,
+    #[cfg(feature = "health")]
+    health: Option<health::HealthHandle>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<(metrics::Metrics, String)>,
+    #[cfg(feature = "tls")]
+    tls: Option<(PathBuf, PathBuf)>,
+}
 
 impl Server {
     /// Create a new [`Server`](Server) builder.
@@ -56,7 +123,328 @@ impl Server {
     /// # Returns
     /// A [`Server`](Server) in default configuration.
     pub fn builder() -> Self {
-        Self {}
+        Self {
+            authenticator: None,
+            origin_policy: OriginPolicy::default(),
+            panic_hook: None,
+            send_buffer: DEFAULT_SEND_BUFFER,
+            overflow_policy: OverflowPolicy::default(),
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            http_fallback: false,
+            #[cfg(feature = "health")]
+            health: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Report handlers, that panicked while processing a call.
+    ///
+    /// A panicking handler only fails the call it was processing with an `INTERNAL` status.
+    /// The connection and all other calls on it are not affected.
+    ///
+    /// # Arguments
+    /// - `f`: the callback, which receives the [`ConnectionInfo`](ConnectionInfo)
+    ///   of the connection, the path of the call and the panic message
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the panic hook installed.
+    pub fn on_panic<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ConnectionInfo, &str, &str) + Send + Sync + 'static,
+    {
+        self.panic_hook = Some(PanicHook::new(f));
+        self
+    }
+
+    /// Limit the number of replies, that are buffered for a connection.
+    ///
+    /// A client, that sends calls faster than it reads the replies, fills the buffer.
+    /// Once it is full, the [`OverflowPolicy`](OverflowPolicy) decides whether the connection
+    /// is paused or closed.
+    /// By default, up to 32 replies are buffered and the connection is paused.
+    ///
+    /// # Arguments
+    /// - `capacity`: the maximum number of buffered replies, must be greater than zero
+    /// - `policy`: the [`OverflowPolicy`](OverflowPolicy) to apply, once the buffer is full
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the new send buffer.
+    ///
+    /// # Panics
+    /// - If `capacity` is zero.
+    pub fn send_buffer(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(
+            capacity > 0,
+            "send buffer capacity must be greater than zero"
+        );
+        self.send_buffer = capacity;
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Close connections, whose client stopped reading the replies.
+    ///
+    /// A connection, that is [blocked](OverflowPolicy::Block) on a full send buffer or waits
+    /// for the client to grant credits to a streamed reply, is closed once it waited for
+    /// `timeout`.
+    /// By default, the connection waits for 30 seconds.
+    ///
+    /// # Arguments
+    /// - `timeout`: the time to wait for the client to catch up
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the new stall timeout.
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// Ping the clients periodically and close connections, that do not answer.
+    ///
+    /// Browsers answer websocket pings automatically.
+    /// A client, that does not answer in time, is considered dead (e.g. a sleeping laptop or
+    /// a dropped NAT mapping) and its connection is dropped.
+    /// Any message from the client counts as an answer.
+    ///
+    /// Pings are only sent between calls.
+    ///
+    /// # Arguments
+    /// - `interval`: the time between two pings
+    /// - `timeout`: the time the client has to answer a ping
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with keepalive pings enabled.
+    pub fn keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.timeouts.ping_interval = Some(interval);
+        self.timeouts.pong_timeout = timeout;
+        self
+    }
+
+    /// Close connections, on which no calls were made for some time.
+    ///
+    /// The connection is closed with the close code `1000` (normal closure).
+    ///
+    /// # Arguments
+    /// - `timeout`: the time after the last call, after which the connection is closed
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the idle timeout enabled.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Close connections, once they have been open for some time.
+    ///
+    /// This forces clients to reconnect periodically, e.g. to pick up new credentials or to
+    /// spread across new server instances.
+    /// A call, that is in progress when the age is reached, is finished first.
+    /// The connection is then closed with the close code `1001` (going away).
+    ///
+    /// # Arguments
+    /// - `age`: the maximum lifetime of a connection
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the maximum connection age enabled.
+    pub fn max_connection_age(mut self, age: Duration) -> Self {
+        self.timeouts.max_age = Some(age);
+        self
+    }
+
+    /// Limit the number of open connections.
+    ///
+    /// Upgrade requests, that exceed the limit, are rejected with `503 Service Unavailable`.
+    ///
+    /// # Arguments
+    /// - `max`: the maximum number of connections to the server
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the connection limit.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.limits.max_connections = Some(max);
+        self
+    }
+
+    /// Limit the number of open connections from a single IP address.
+    ///
+    /// Upgrade requests, that exceed the limit, are rejected with `429 Too Many Requests`.
+    ///
+    /// **Note**: Behind a reverse proxy, all connections appear to come from the proxy.
+    ///
+    /// # Arguments
+    /// - `max`: the maximum number of connections per IP address
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the per IP connection limit.
+    pub fn max_connections_per_ip(mut self, max: usize) -> Self {
+        self.limits.max_connections_per_ip = Some(max);
+        self
+    }
+
+    /// Limit the rate of calls on each connection.
+    ///
+    /// Every connection has a token bucket, that holds up to `burst` tokens and is refilled
+    /// with `rate` tokens per second.
+    /// Calls, that find the bucket empty, fail with `RESOURCE_EXHAUSTED` without reaching
+    /// their service.
+    ///
+    /// # Arguments
+    /// - `rate`: the sustained number of calls per second
+    /// - `burst`: the number of calls, that can be made at once
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the call rate limit.
+    pub fn call_rate(mut self, rate: u32, burst: u32) -> Self {
+        self.limits.call_rate = Some((rate, burst));
+        self
+    }
+
+    /// Limit the headers of a call.
+    ///
+    /// Calls, that exceed a limit, fail with `RESOURCE_EXHAUSTED` without reaching their service.
+    ///
+    /// # Arguments
+    /// - `count`: the maximum number of headers
+    /// - `bytes`: the maximum size of all header names and values combined
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the header limits.
+    pub fn max_headers(mut self, count: usize, bytes: usize) -> Self {
+        self.limits.max_header_count = Some(count);
+        self.limits.max_header_bytes = Some(bytes);
+        self
+    }
+
+    /// Serve the status of a [`HealthHandle`](health::HealthHandle) as plain HTTP on `/healthz`.
+    ///
+    /// The endpoint answers `200 OK` if all services are serving and `503 Service Unavailable`
+    /// otherwise.
+    /// The status of a single service can be queried with `/healthz?service=<name>`.
+    ///
+    /// # Arguments
+    /// - `handle`: the [`HealthHandle`](health::HealthHandle) to report
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the health endpoint enabled.
+    #[cfg(feature = "health")]
+    pub fn healthz(mut self, handle: health::HealthHandle) -> Self {
+        self.health = Some(handle);
+        self
+    }
+
+    /// Set the [`OriginPolicy`](OriginPolicy) of the server.
+    ///
+    /// Upgrade requests from origins, that are not allowed by the policy, are rejected with
+    /// `403 Forbidden`.
+    ///
+    /// # Arguments
+    /// - `policy`: the [`OriginPolicy`](OriginPolicy) to apply
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the new origin policy.
+    pub fn origin_policy(mut self, policy: OriginPolicy) -> Self {
+        self.origin_policy = policy;
+        self
+    }
+
+    /// Accept calls over plain HTTP, for clients that can not open a websocket.
+    ///
+    /// Some proxies block websocket upgrades.
+    /// A client behind such a proxy can instead `POST` every call, as an encoded
+    /// [`Call`](Call) with the content type
+    /// [`HTTP_CONTENT_TYPE`](webtonic_proto::HTTP_CONTENT_TYPE), to the path of the tunnel.
+    /// The server answers with the encoded [`Reply`](webtonic_proto::Reply).
+    ///
+    /// Every call is treated as a connection of its own, which is subject to the
+    /// [`OriginPolicy`](OriginPolicy), the connection limits and the
+    /// [authentication](Server::authenticate).
+    /// The [call rate](Server::call_rate) is limited per IP address instead.
+    ///
+    /// Calls with any other content type are rejected with `415 Unsupported Media Type`.
+    /// This forces browsers to send a CORS preflight before every cross origin call, which is
+    /// only answered for the origins allowed by the [`OriginPolicy`](OriginPolicy).
+    /// The cookies of the page, which the calls of `webtonic-client` include, are only sent
+    /// along, if the policy is an [allow list](OriginPolicy::allow_list) or a
+    /// [predicate](OriginPolicy::predicate).
+    /// With [any origin](OriginPolicy::any), other pages can make calls, but only without
+    /// the cookies of their visitors.
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with the HTTP fallback enabled.
+    pub fn http_fallback(mut self) -> Self {
+        self.http_fallback = true;
+        self
+    }
+
+    /// Authenticate clients, when they upgrade their connection to a websocket.
+    ///
+    /// Browsers can not set arbitrary headers on websocket connections.
+    /// The credentials therefore need to be transmitted as cookies, in the query string
+    /// or in the `Sec-WebSocket-Protocol` header, all of which are available through the
+    /// [`ConnectionInfo`](ConnectionInfo) passed to the callback.
+    ///
+    /// If the callback returns `Some(identity)`, the connection is accepted and the identity
+    /// is inserted into the extensions of every call made over that connection.
+    /// If it returns `None`, the upgrade is rejected with `401 Unauthorized`.
+    ///
+    /// # Arguments
+    /// - `f`: the async authentication callback
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with authentication enabled.
+    ///
+    /// # Example
+    /// ```ignore
+    /// webtonic_server::Server::builder()
+    ///     .authenticate(|info: ConnectionInfo| async move {
+    ///         let token = info.query()?.strip_prefix("token=")?.to_string();
+    ///         lookup_user(&token).await
+    ///     })
+    ///     .add_service(GreeterServer::new(greeter))
+    ///     .serve(([127, 0, 0, 1], 8080))
+    ///     .await;
+    /// ```
+    pub fn authenticate<F, Fut, I>(mut self, f: F) -> Self
+    where
+        F: Fn(ConnectionInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<I>> + Send + 'static,
+        I: Clone + Send + Sync + 'static,
+    {
+        self.authenticator = Some(Authenticator::new(f));
+        self
+    }
+
+    /// Record [`Metrics`](metrics::Metrics) and serve them in the Prometheus text format.
+    ///
+    /// # Arguments
+    /// - `metrics`: the [`Metrics`](metrics::Metrics) to record into
+    /// - `path`: the HTTP path on which to serve the metrics, e.g. `/metrics`
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with metrics enabled.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: metrics::Metrics, path: &str) -> Self {
+        self.metrics = Some((metrics, path.to_string()));
+        self
+    }
+
+    /// Serve the tunnel over TLS, such that clients can connect with `wss://`.
+    ///
+    /// # Arguments
+    /// - `cert`: the path of the PEM encoded certificate chain
+    /// - `key`: the path of the PEM encoded private key
+    ///
+    /// # Returns
+    /// - The [`Server`](Server) with TLS enabled.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.tls = Some((cert.into(), key.into()));
+        self
     }
 
     /// [service]: https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html
@@ -68,43 +456,96 @@ impl Server {
     /// # Returns
     /// - A [`Router`](Router), which included the old routes and the new service.
     /// This also means you need to finish server configuration before calling this function.
-    pub fn add_service<A>(self, service: A) -> Router<A, Unimplemented>
+    pub fn add_service<S>(self, service: S) -> Router
     where
-        A: Service<Request<BoxBody>, Response = Response<BoxBody>> + Sync + Send + 'static,
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>>
+            + NamedService
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Error: Into<Box<dyn Error + Send + Sync>>,
+        S::Future: Send + 'static,
     {
-        Router {
-            server: self,
-            root: Route(service, Unimplemented),
-        }
+        self.router().add_service(service)
     }
-}
 
-/// A [`Router`](Router) is used to compile [`Routes`](Route), by [adding services](Router::add_service).
-#[derive(Debug, Clone)]
-pub struct Router<A, B> {
-    server: Server,
-    root: Route<A, B>,
-}
+    /// [service]: https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html
+    /// Add a [`Service`][service] to the route, if it is `Some` (see [`Router::add_optional_service`]).
+    ///
+    /// # Arguments
+    /// - `service`: the optional [`Service`][service] to add
+    ///
+    /// # Returns
+    /// - A [`Router`](Router), which includes the new service, if there was one.
+    pub fn add_optional_service<S>(self, service: Option<S>) -> Router
+    where
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>>
+            + NamedService
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Error: Into<Box<dyn Error + Send + Sync>>,
+        S::Future: Send + 'static,
+    {
+        self.router().add_optional_service(service)
+    }
 
-impl<A, B> Router<A, B> {
     /// [service]: https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html
-    /// Add a [`Service`][service] to the route (see [example](Server)).
+    /// Serve an existing [`Service`][service], e.g. a tonic router wrapped in
+    /// [`TonicRouter`](TonicRouter), over the tunnel.
+    ///
+    /// The service receives all calls, that are not routed to a service added to the returned
+    /// [`Router`](Router) (see [`Router::fallback`]).
     ///
     /// # Arguments
-    /// - `service`: the [`Service`][service] to add
+    /// - `routes`: the [`Service`][service], that routes the calls
     ///
     /// # Returns
-    /// - A new [`Router`](Router), which included the old routes and the new service.
-    pub fn add_service<C>(self, service: C) -> Router<C, Route<A, B>>
+    /// - A [`Router`](Router), which routes all calls to `routes`.
+    pub fn add_routes<S>(self, routes: S) -> Router
     where
-        C: Service<Request<BoxBody>, Response = Response<BoxBody>, Error = Never>,
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>> + Clone + Send + Sync + 'static,
+        S::Error: Into<Box<dyn Error + Send + Sync>>,
+        S::Future: Send + 'static,
     {
-        Router {
-            server: self.server,
-            root: Route(service, self.root),
+        self.router().fallback(routes)
+    }
+
+    /// Finish the server configuration and create an empty [`Router`](Router).
+    ///
+    /// This is useful, if the services are only known at runtime.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut router = webtonic_server::Server::builder().router();
+    /// for (name, service) in services {
+    ///     router = router.add_named_service(name, service);
+    /// }
+    /// router.serve(([127, 0, 0, 1], 8080)).await;
+    /// ```
+    pub fn router(self) -> Router {
+        Router::new(self)
+    }
+
+    /// Logs and counts a rejection by one of the [`Limits`](Limits).
+    fn limit_exceeded(&self, limit: Limit, info: &ConnectionInfo) {
+        log::info!(
+            "connection {} from {:?} exceeded the {} limit",
+            info.id(),
+            info.remote_addr(),
+            limit.as_str()
+        );
+
+        #[cfg(feature = "metrics")]
+        if let Some((metrics, _)) = &self.metrics {
+            metrics.limit_exceeded(limit);
         }
     }
+}
 
+impl Router {
     /// Start serving the endpoint on the provided addres (see [example](Server)).
     ///
     /// # Arguments
@@ -115,108 +556,409 @@ impl<A, B> Router<A, B> {
     pub async fn serve<U>(self, addr: U)
     where
         U: Into<SocketAddr>,
-        A: Service<Request<BoxBody>, Response = Response<BoxBody>, Error = Never>
-            + NamedService
-            + Clone
-            + Send
-            + Sync
-            + 'static,
-        A::Future: Send + 'static,
-        B: Service<(String, Request<BoxBody>), Response = Response<BoxBody>, Error = Never>
-            + Clone
-            + Send
-            + Sync
-            + 'static,
-        B::Future: Send + 'static,
     {
-        let server_clone = warp::any().map(move || self.clone());
+        #[cfg(feature = "tls")]
+        let tls = self.server.tls.clone();
+        let tunnel = filter(Arc::new(self));
 
-        warp::serve(warp::path::end().and(warp::ws()).and(server_clone).map(
-            |ws: warp::ws::Ws, server_clone| {
-                ws.on_upgrade(|socket| handle_connection2(socket, server_clone))
-            },
-        ))
-        .run(addr)
-        .await;
+        #[cfg(feature = "tls")]
+        if let Some((cert, key)) = tls {
+            warp::serve(tunnel)
+                .tls()
+                .cert_path(cert)
+                .key_path(key)
+                .run(addr)
+                .await;
+            return;
+        }
+
+        warp::serve(tunnel).run(addr).await;
     }
 }
 
-/// Representation of a gRPC route.
+/// Creates the [`Filter`](warp::Filter), that serves the tunnel of `router` and its HTTP endpoints.
+fn filter(
+    router: Arc<Router>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static {
+    let server_clone = warp::any().map(move || router.clone());
+    let query = warp::query::raw()
+        .map(Some)
+        .or(warp::any().map(|| None))
+        .unify();
+
+    let tunnel = warp::path::end()
+        .and(warp::ws())
+        .and(warp::addr::remote())
+        .and(warp::header::headers_cloned())
+        .and(query)
+        .and(server_clone.clone())
+        .and_then(upgrade);
+
+    let tunnel = tunnel
+        .or(warp::path::end()
+            .and(warp::post())
+            .and(warp::addr::remote())
+            .and(warp::header::headers_cloned())
+            .and(query)
+            .and(warp::body::content_length_limit(MAX_HTTP_CALL_SIZE))
+            .and(warp::body::bytes())
+            .and(server_clone.clone())
+            .and_then(http_call))
+        .or(warp::path::end()
+            .and(warp::options())
+            .and(warp::header::headers_cloned())
+            .and(server_clone.clone())
+            .and_then(http_preflight));
+
+    #[cfg(feature = "health")]
+    let tunnel = tunnel.or(warp::path("healthz")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(server_clone.clone())
+        .and_then(healthz));
+
+    #[cfg(feature = "metrics")]
+    let tunnel = tunnel.or(warp::get()
+        .and(warp::path::full())
+        .and(server_clone)
+        .and_then(serve_metrics));
+
+    tunnel
+}
+
+/// Checks the origin, the limits and the credentials of a client, that opens a connection.
 ///
-/// You will likely not interact with this directly, but rather through the [`Server`](Server)
-/// and [`Router`](Router) structs.
-#[derive(Debug, Clone)]
-pub struct Route<A, B>(A, B);
-
-impl<A, B> Service<(String, Request<BoxBody>)> for Route<A, B>
-where
-    A: Service<Request<BoxBody>, Response = Response<BoxBody>, Error = Never> + NamedService,
-    A::Future: Send + 'static,
-    B: Service<(String, Request<BoxBody>), Response = Response<BoxBody>, Error = Never>,
-    B::Future: Send + 'static,
-{
-    type Response = Response<BoxBody>;
-    type Error = Never;
-    type Future = future::Either<A::Future, B::Future>;
-
-    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Ok(()).into()
-    }
-
-    fn call(&mut self, req: (String, Request<BoxBody>)) -> Self::Future {
-        if req.0.eq(<A as NamedService>::NAME) {
-            future::Either::Left(self.0.call(req.1))
-        } else {
-            future::Either::Right(self.1.call((req.0, req.1)))
+/// # Returns
+/// - The [`ConnectionInfo`](ConnectionInfo) of the connection and its permit, if the client
+///   is admitted.
+/// - The HTTP status, with which the client is rejected otherwise.
+async fn admit(
+    router: &Router,
+    remote_addr: Option<SocketAddr>,
+    headers: HeaderMap,
+    query: Option<String>,
+) -> Result<(ConnectionInfo, ConnectionPermit), StatusCode> {
+    let origin = headers
+        .get("origin")
+        .map(|origin| origin.to_str().unwrap_or_default());
+    if !router.server.origin_policy.allows(origin) {
+        log::warn!(
+            "rejected connection from {:?} with disallowed origin {:?}",
+            remote_addr,
+            origin
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut info = ConnectionInfo::new(remote_addr, headers, query);
+
+    // Check the limits before authenticating, which may be expensive
+    let permit = match router
+        .server
+        .limits
+        .acquire(remote_addr.map(|addr| addr.ip()))
+    {
+        Ok(permit) => permit,
+        Err(limit) => {
+            router.server.limit_exceeded(limit, &info);
+            return Err(match limit {
+                Limit::ConnectionsPerIp => StatusCode::TOO_MANY_REQUESTS,
+                _ => StatusCode::SERVICE_UNAVAILABLE,
+            });
+        }
+    };
+
+    if let Some(authenticator) = &router.server.authenticator {
+        match authenticator.authenticate(info.clone()).await {
+            Some(identity) => info.set_identity(identity),
+            None => {
+                log::info!(
+                    "rejected unauthenticated connection {} from {:?}",
+                    info.id(),
+                    info.remote_addr()
+                );
+                return Err(StatusCode::UNAUTHORIZED);
+            }
         }
     }
+
+    Ok((info, permit))
 }
 
-/// The unimplemented service sends `unimplemented` errors on any request.
-///
-/// This is used as the fallthrough route in gRPC.
-#[derive(Default, Clone, Debug)]
-pub struct Unimplemented;
+async fn upgrade(
+    ws: warp::ws::Ws,
+    remote_addr: Option<SocketAddr>,
+    headers: HeaderMap,
+    query: Option<String>,
+    router: Arc<Router>,
+) -> Result<warp::reply::Response, Infallible> {
+    // If the client offered subprotocols, we need to accept one of them,
+    // otherwise the browser fails the connection
+    let protocol = headers
+        .get("sec-websocket-protocol")
+        .and_then(|protocols| protocols.to_str().ok())
+        .and_then(|protocols| protocols.split(',').next())
+        .map(|protocol| protocol.trim().to_string());
+
+    let (info, permit) = match admit(&router, remote_addr, headers, query).await {
+        Ok(admitted) => admitted,
+        Err(status) => return Ok(status.into_response()),
+    };
+
+    #[cfg(feature = "tracing")]
+    let reply = {
+        use tracing::Instrument;
+        let span = trace::connection_span(&info);
+        ws.on_upgrade(|socket| handle_connection2(socket, router, info, permit).instrument(span))
+    };
+    #[cfg(not(feature = "tracing"))]
+    let reply = ws.on_upgrade(|socket| handle_connection2(socket, router, info, permit));
+    Ok(match protocol {
+        Some(protocol) => {
+            warp::reply::with_header(reply, "sec-websocket-protocol", protocol).into_response()
+        }
+        None => reply.into_response(),
+    })
+}
+
+/// Processes a single call, that was sent over HTTP instead of a websocket.
+async fn http_call(
+    remote_addr: Option<SocketAddr>,
+    headers: HeaderMap,
+    query: Option<String>,
+    body: Bytes,
+    router: Arc<Router>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    if !router.server.http_fallback {
+        return Err(warp::reject::not_found());
+    }
+
+    // Browsers send other content types cross origin without a preflight
+    if !is_call_content_type(&headers) {
+        log::warn!(
+            "rejected call over HTTP from {:?} with content type {:?}",
+            remote_addr,
+            headers.get(CONTENT_TYPE)
+        );
+        return Ok(StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response());
+    }
+
+    let origin = headers.get("origin").cloned();
+    let (info, _permit) = match admit(&router, remote_addr, headers, query).await {
+        Ok(admitted) => admitted,
+        Err(status) => return Ok(status.into_response()),
+    };
+    log::debug!(
+        "processing call {} over HTTP from {:?}",
+        info.id(),
+        info.remote_addr()
+    );
 
-impl Service<(String, Request<BoxBody>)> for Unimplemented {
-    type Response = Response<BoxBody>;
-    type Error = Never;
-    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+    let remote_ip = info.remote_addr().map(|addr| addr.ip());
+    let request_bytes = body.len();
+    let reply = match Call::decode(body) {
+        Ok(_) if !router.server.limits.try_acquire_http_call(remote_ip) => {
+            router.server.limit_exceeded(Limit::CallRate, &info);
+            Err(Status::resource_exhausted("call rate exceeded"))
+        }
+        // The client checks, whether calls over HTTP are accepted
+        Ok(call) if call.is_probe() => Ok(status_reply(Status::new(tonic::Code::Ok, "")).await),
+        Ok(call) => process_call(&router, &info, call, request_bytes, None).await,
+        Err(e) => Err(Status::invalid_argument(format!(
+            "failed to decode call {:?}",
+            e
+        ))),
+    };
+    let reply = match reply {
+        Ok(reply) => reply,
+        Err(status) => {
+            log::warn!("error while processing call, returning status {:?}", status);
+            status_reply(status).await
+        }
+    };
+
+    let mut response = Response::new(hyper::Body::from(reply.freeze()));
+    let headers = response.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(webtonic_proto::HTTP_CONTENT_TYPE),
+    );
+    allow_origin(headers, origin, &router.server.origin_policy);
+    Ok(response)
+}
 
-    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Ok(()).into()
+/// Returns `true`, if the content type of a call over HTTP is
+/// [`HTTP_CONTENT_TYPE`](webtonic_proto::HTTP_CONTENT_TYPE).
+fn is_call_content_type(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.split(';').next())
+        .is_some_and(|essence| {
+            essence
+                .trim()
+                .eq_ignore_ascii_case(webtonic_proto::HTTP_CONTENT_TYPE)
+        })
+}
+
+/// Answers the CORS preflight request of a browser, that is about to make a call over HTTP.
+async fn http_preflight(
+    headers: HeaderMap,
+    router: Arc<Router>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    if !router.server.http_fallback {
+        return Err(warp::reject::not_found());
     }
 
-    fn call(&mut self, _req: (String, Request<BoxBody>)) -> Self::Future {
-        future::ok(
-            http::Response::builder()
-                .status(200)
-                .header("grpc-status", "12")
-                .header("content-type", "application/grpc")
-                .body(empty_body())
-                .unwrap(),
-        )
+    let origin = headers.get("origin").cloned();
+    let allowed = origin
+        .as_ref()
+        .map(|origin| origin.to_str().unwrap_or_default());
+    if !router.server.origin_policy.allows(allowed) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
     }
+
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("POST"),
+    );
+    headers.insert(
+        ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("content-type"),
+    );
+    headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static("86400"));
+    allow_origin(headers, origin, &router.server.origin_policy);
+    Ok(response)
 }
 
-async fn handle_connection2<A, B>(ws: WebSocket, routes: Router<A, B>)
-where
-    A: Service<Request<BoxBody>, Response = Response<BoxBody>, Error = Never>
-        + NamedService
-        + Clone,
-    A::Future: Send + 'static,
-    B: Service<(String, Request<BoxBody>), Response = Response<BoxBody>, Error = Never> + Clone,
-    B::Future: Send + 'static,
-{
-    log::debug!("opening a new connection");
+/// Allows the browser to read the response from `origin`.
+///
+/// The origin must have been checked against the [`OriginPolicy`](OriginPolicy).
+/// The cookies are only included, if the `policy` restricts the origins.
+fn allow_origin(headers: &mut HeaderMap, origin: Option<HeaderValue>, policy: &OriginPolicy) {
+    if let Some(origin) = origin {
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        // Otherwise, any site could make calls with the cookies of its visitors
+        if policy.is_restrictive() {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        headers.insert(VARY, HeaderValue::from_static("origin"));
+    }
+}
+
+#[cfg(feature = "health")]
+async fn healthz(
+    query: HashMap<String, String>,
+    router: Arc<Router>,
+) -> Result<impl Reply, warp::Rejection> {
+    let handle = match &router.server.health {
+        Some(handle) => handle,
+        None => return Err(warp::reject::not_found()),
+    };
+
+    let (status, body) = handle
+        .http_status(query.get("service").map(String::as_str))
+        .await;
+    Ok(warp::reply::with_status(body, status))
+}
+
+#[cfg(feature = "metrics")]
+async fn serve_metrics(
+    path: warp::path::FullPath,
+    router: Arc<Router>,
+) -> Result<impl Reply, warp::Rejection> {
+    match &router.server.metrics {
+        Some((metrics, metrics_path)) if path.as_str() == metrics_path => Ok(
+            warp::reply::with_header(metrics.encode(), "content-type", prometheus::TEXT_FORMAT),
+        ),
+        _ => Err(warp::reject::not_found()),
+    }
+}
+
+async fn handle_connection2(
+    ws: WebSocket,
+    routes: Arc<Router>,
+    info: ConnectionInfo,
+    _permit: ConnectionPermit,
+) {
+    log::debug!(
+        "opening a new connection {} from {:?}",
+        info.id(),
+        info.remote_addr()
+    );
+
+    #[cfg(feature = "metrics")]
+    let _connection_guard = routes
+        .server
+        .metrics
+        .as_ref()
+        .map(|(metrics, _)| metrics.connection());
 
     let (ws_tx, mut ws_rx) = ws.split();
-    let (tx, rx) = unbounded_channel();
+    let (tx, rx) = Outbound::new(
+        routes.server.send_buffer,
+        routes.server.overflow_policy,
+        routes.server.stall_timeout,
+    );
+    #[cfg(feature = "metrics")]
+    let tx = tx.with_metrics(
+        routes
+            .server
+            .metrics
+            .as_ref()
+            .map(|(metrics, _)| metrics.clone()),
+    );
     // Create outbound task
-    tokio::task::spawn(UnboundedReceiverStream::new(rx).forward(ws_tx));
+    let outbound = tokio::task::spawn(ReceiverStream::new(rx).forward(ws_tx));
 
-    while let Some(msg) = ws_rx.next().await {
-        log::debug!("received message {:?}", msg);
+    let mut keepalive = Keepalive::new(routes.server.timeouts);
+    let mut call_limiter = routes.server.limits.call_limiter();
+    // The messages, that were received while streaming a reply
+    let mut backlog = VecDeque::new();
+
+    loop {
+        let msg = match backlog.pop_front() {
+            Some(msg) => Ok(msg),
+            None => tokio::select! {
+            msg = ws_rx.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            event = keepalive.next() => match event {
+                Event::Ping => {
+                    keepalive.ping_sent();
+                    match tx.send(Message::ping(Vec::new())).await {
+                        Ok(()) => continue,
+                        Err(_) => break,
+                    }
+                }
+                Event::PongTimeout => {
+                    log::info!("connection {} did not answer the ping", info.id());
+                    break;
+                }
+                Event::Idle => {
+                    log::debug!("closing idle connection {}", info.id());
+                    let _ = tx.send(Message::close_with(1000u16, "idle timeout")).await;
+                    break;
+                }
+                Event::MaxAge => {
+                    log::debug!("closing connection {}, it reached its maximum age", info.id());
+                    let _ = tx
+                        .send(Message::close_with(1001u16, "maximum connection age"))
+                        .await;
+                    break;
+                }
+            },
+            },
+        };
+        keepalive.received();
+        log::trace!("received message {:?}", msg);
 
         // Try to send status error
         // If even that fails, end task
@@ -237,6 +979,9 @@ where
                 } else if msg.is_close() {
                     log::debug!("channel was closed");
                     break;
+                } else if msg.is_ping() || msg.is_pong() {
+                    // Pings are answered by the websocket itself
+                    continue;
                 } else {
                     status_err!(Status::invalid_argument(
                         "websocket messages must be sent in binary"
@@ -248,64 +993,862 @@ where
                 e
             ))),
         };
-
-        // Parse message first into protobuf then into http request
+        let request_bytes = msg.len();
         let call = match Call::decode(msg) {
             Ok(call) => call,
-            Err(e) => status_err!(Status::internal(&format!("failed to decode call {:?}", e))),
+            Err(e) => status_err!(Status::internal(format!("failed to decode call {:?}", e))),
         };
-        let call = webtonic_proto::call_to_http_request(call).unwrap();
-
-        // Get the path to the requested service
-        let path: &str = call
-            .uri()
-            .path()
-            .split("/")
-            .collect::<Vec<&str>>()
-            .get(1)
-            .unwrap_or(&&"/");
-        log::debug!("request to path {:?}", path);
-
-        let mut response = match routes.root.clone().call((path.to_string(), call)).await {
-            Ok(response) => response,
-            Err(_e) => {
-                panic!("Tonic services never error");
+
+        // Credits, that arrive after their stream ended, are not needed any more
+        if call.is_grant() {
+            continue;
+        }
+
+        if let Some(limiter) = &mut call_limiter {
+            if !limiter.try_acquire() {
+                routes.server.limit_exceeded(Limit::CallRate, &info);
+                status_err!(Status::resource_exhausted("call rate exceeded"))
             }
-        };
-        log::debug!("got response {:?}", response);
+        }
 
-        // Turn reply first into protobuf, then into message
-        let reply = webtonic_proto::http_response_to_reply(&mut response).await;
-        let mut msg = BytesMut::new();
-        match reply.encode(&mut msg) {
-            Ok(()) => (),
-            Err(e) => status_err!(Status::internal(&format!("failed to decode reply {:?}", e))),
+        let mut stream = ReplyStream::new(&tx, &mut ws_rx, &mut backlog);
+        let reply = process_call(&routes, &info, call, request_bytes, Some(&mut stream)).await;
+        // Failed calls restart the idle timeout as well
+        keepalive.call_finished();
+        let msg = match reply {
+            Ok(reply) => reply,
+            Err(status) => status_err!(status),
         };
-        let msg = Message::binary(msg.as_ref());
 
-        // Return the message
-        log::debug!("sending response {:?}", msg);
-        match tx.send(Ok(msg)) {
+        // Return the message, unless the stream already broke the connection
+        let sent = match stream.error.take() {
+            Some(e) => Err(e),
+            None => {
+                let msg = Message::binary(msg.as_ref());
+                log::trace!("sending response {:?}", msg);
+                tx.send(msg).await
+            }
+        };
+        match sent {
             Ok(()) => (),
-            Err(e) => {
-                log::warn!("stream no longer exists {:?}", e);
+            Err(SendError::Closed) => {
+                log::warn!("stream of connection {} no longer exists", info.id());
+                break;
+            }
+            Err(SendError::Flooded) => {
+                log::warn!(
+                    "closing connection {}, the client sent too many calls while streaming",
+                    info.id()
+                );
+                let _ = tx
+                    .send(Message::close_with(1008u16, "too many calls"))
+                    .await;
+                break;
+            }
+            Err(e @ SendError::Full) | Err(e @ SendError::Stalled) => {
+                log::warn!(
+                    "closing connection {}, the client does not keep up with the replies ({:?})",
+                    info.id(),
+                    e
+                );
+                // Drop the buffered replies along with the websocket
+                outbound.abort();
                 break;
             }
         }
     }
 }
 
-async fn return_status(tx: &UnboundedSender<Result<Message, warp::Error>>, status: Status) -> bool {
-    log::warn!("error while processing msg, returning status {:?}", status);
+/// Processes an encoded [`Call`](Call), received from the client of the connection `info`.
+///
+/// # Returns
+/// - The encoded [`Reply`](webtonic_proto::Reply) of the service.
+/// - A status, if the call could not be processed.
+async fn process_call(
+    routes: &Router,
+    info: &ConnectionInfo,
+    call: Call,
+    request_bytes: usize,
+    stream: Option<&mut ReplyStream<'_>>,
+) -> Result<BytesMut, Status> {
+    let started = Instant::now();
+
+    // Stream the reply, if the client granted credits
+    let credits = call.credits();
+    let stream = stream.filter(|_| credits > 0);
+    let streaming = stream.is_some();
+
+    // Parse the call into a http request
+    let mut call = webtonic_proto::call_to_http_request(call)
+        .ok_or_else(|| Status::invalid_argument("malformed call"))?;
+
+    if let Err(limit) = routes.server.limits.check_headers(call.headers()) {
+        routes.server.limit_exceeded(limit, info);
+        return Err(Status::resource_exhausted("headers exceed the limits"));
+    }
+    info.insert_into(call.extensions_mut());
+
+    // Get the service and method from the path `/<service>/<method>`
+    let uri_path = call.uri().path().to_string();
+    let mut segments = uri_path.split('/').skip(1);
+    let service = segments.next().unwrap_or_default();
+    let method = segments.next().unwrap_or_default();
+    log::debug!(
+        "connection {} called {:?} on service {:?}",
+        info.id(),
+        method,
+        service
+    );
+
+    #[cfg(feature = "tracing")]
+    let span = trace::call_span(&call, service, method);
+    let response = routes.route(call).then(|mut response| async move {
+        log::trace!("got response {:?}", response);
+        match stream {
+            Some(stream) => {
+                stream.start(credits);
+                let reply = stream_reply(&mut response, stream).await;
+                (reply, stream.sent)
+            }
+            None => (
+                webtonic_proto::http_response_to_reply(&mut response).await,
+                0,
+            ),
+        }
+    });
+    #[cfg(feature = "tracing")]
+    let response = tracing::Instrument::instrument(response, span.clone());
+
+    // A panicking handler must not take down the other calls on this connection
+    let (reply, streamed) = match AssertUnwindSafe(response).catch_unwind().await {
+        Ok(replied) => replied,
+        Err(panic) => {
+            let message = panic::panic_message(&*panic);
+            log::error!("handler of {:?} panicked: {}", uri_path, message);
+            if let Some(hook) = &routes.server.panic_hook {
+                hook.report(info, &uri_path, message);
+            }
+
+            // The status is part of the trailers, if the response was already streamed
+            let status = Status::internal("handler panicked");
+            let reply = match streaming {
+                true => webtonic_proto::Reply::status_trailers(status),
+                false => webtonic_proto::http_response_to_reply(&mut status.to_http()).await,
+            };
+            (reply, 0)
+        }
+    };
+
+    // Turn reply into message
+    let mut msg = BytesMut::new();
+    reply
+        .encode(&mut msg)
+        .map_err(|e| Status::internal(&format!("failed to decode reply {:?}", e)))?;
+
+    let code = reply.grpc_status().unwrap_or(tonic::Code::Unknown as i32);
+    log::debug!(
+        "connection {} call to {:?} finished with status {}",
+        info.id(),
+        uri_path,
+        code
+    );
+
+    #[cfg(feature = "tracing")]
+    trace::record_call(&span, code, started.elapsed());
+
+    #[cfg(feature = "metrics")]
+    if let Some((metrics, _)) = &routes.server.metrics {
+        metrics.record_call(&metrics::CallRecord {
+            service,
+            method,
+            code,
+            duration: started.elapsed(),
+            request_bytes,
+            response_bytes: streamed + msg.len(),
+        });
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (started, request_bytes, streamed);
+
+    Ok(msg)
+}
+
+/// Sends the parts of a streamed reply, as far as the client granted credits.
+///
+/// # Returns
+/// - The last part of the reply, which is sent like a collected reply.
+/// - A status, if sending the parts failed. The error is kept in `stream`.
+async fn stream_reply(
+    response: &mut Response<BoxBody>,
+    stream: &mut ReplyStream<'_>,
+) -> webtonic_proto::Reply {
+    let mut parts = ReplyParts::new(response);
+    loop {
+        let part = parts.next().await;
+        if !part.is_partial() {
+            return part;
+        }
+
+        if let Err(e) = stream.send(part).await {
+            stream.error = Some(e);
+            return webtonic_proto::Reply::status_trailers(Status::cancelled(
+                "the client stopped reading the reply",
+            ));
+        }
+    }
+}
+
+/// Encodes a [`Reply`](webtonic_proto::Reply), that fails a call with `status`.
+async fn status_reply(status: Status) -> BytesMut {
     let mut response = status.to_http();
 
     let reply = webtonic_proto::http_response_to_reply(&mut response).await;
     let mut msg = BytesMut::new();
     reply.encode(&mut msg).unwrap();
-    let msg = Message::binary(msg.as_ref());
+    msg
+}
 
-    match tx.send(Ok(msg)) {
+async fn return_status(tx: &Outbound, status: Status) -> bool {
+    log::warn!("error while processing msg, returning status {:?}", status);
+    let msg = Message::binary(status_reply(status).await.as_ref());
+
+    match tx.send(msg).await {
         Ok(()) => true,
         Err(_) => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::test::WsClient;
+
+    const ORIGIN: &str = "https://example.com";
+
+    fn client() -> Option<SocketAddr> {
+        Some(SocketAddr::from(([127, 0, 0, 1], 40000)))
+    }
+
+    fn origin_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("origin", HeaderValue::from_static(ORIGIN));
+        headers
+    }
+
+    fn call_headers() -> HeaderMap {
+        let mut headers = origin_headers();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(webtonic_proto::HTTP_CONTENT_TYPE),
+        );
+        headers
+    }
+
+    async fn post(router: &Arc<Router>, headers: HeaderMap, body: Bytes) -> warp::reply::Response {
+        http_call(client(), headers, None, body, router.clone())
+            .await
+            .unwrap()
+    }
+
+    async fn probe(router: &Arc<Router>) -> warp::reply::Response {
+        let call = Call::probe().encode_to_vec();
+        post(router, call_headers(), call.into()).await
+    }
+
+    async fn grpc_status(response: warp::reply::Response) -> Option<i32> {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        webtonic_proto::Reply::decode(body).unwrap().grpc_status()
+    }
+
+    /// Opens a websocket to the tunnel of `router`.
+    async fn connect(router: Router) -> WsClient {
+        warp::test::ws()
+            .header("origin", ORIGIN)
+            .handshake(filter(Arc::new(router)))
+            .await
+            .unwrap()
+    }
+
+    /// A service, that answers every call with its function.
+    #[derive(Clone)]
+    struct Handler<F>(F);
+
+    impl<F> Service<Request<BoxBody>> for Handler<F>
+    where
+        F: Fn(Request<BoxBody>) -> Response<BoxBody>,
+    {
+        type Response = Response<BoxBody>;
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut core::task::Context<'_>,
+        ) -> core::task::Poll<Result<(), Self::Error>> {
+            Ok(()).into()
+        }
+
+        fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
+            futures::future::ok((self.0)(request))
+        }
+    }
+
+    /// Answers a call with its own message.
+    fn echo(request: Request<BoxBody>) -> Response<BoxBody> {
+        Response::builder()
+            .header(CONTENT_TYPE, "application/grpc")
+            .header("grpc-status", "0")
+            .body(request.into_body())
+            .unwrap()
+    }
+
+    /// Creates a unary call to `path`, carrying the encoded `message`.
+    async fn unary(path: &str, message: &[u8]) -> Call {
+        use bytes::BufMut;
+        use http_body::Body as _;
+
+        let mut body = BytesMut::new();
+        body.put_u8(0);
+        body.put_u32(message.len() as u32);
+        body.put_slice(message);
+        let body = http_body::Full::new(body.freeze()).map_err(|never| match never {});
+
+        let mut request = Request::post(path)
+            .header(CONTENT_TYPE, "application/grpc")
+            .body(BoxBody::new(body))
+            .unwrap();
+        webtonic_proto::http_request_to_call(&mut request)
+            .await
+            .unwrap()
+    }
+
+    /// Returns the encoded message of a unary reply.
+    async fn message(reply: webtonic_proto::Reply) -> Bytes {
+        let response = webtonic_proto::reply_to_http_response(reply).unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        body.slice(5..)
+    }
+
+    /// Makes a `call` over the websocket and waits for its reply.
+    async fn call(ws: &mut WsClient, call: Call) -> webtonic_proto::Reply {
+        ws.send(Message::binary(call.encode_to_vec())).await;
+        let msg = ws.recv().await.unwrap();
+        webtonic_proto::Reply::decode(msg.as_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn http_probe() {
+        let router = Arc::new(Server::builder().http_fallback().router());
+
+        let response = probe(&router).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            webtonic_proto::HTTP_CONTENT_TYPE
+        );
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], ORIGIN);
+        assert_eq!(grpc_status(response).await, Some(tonic::Code::Ok as i32));
+    }
+
+    #[tokio::test]
+    async fn http_content_type() {
+        let router = Arc::new(Server::builder().http_fallback().router());
+        let call = Bytes::from(Call::probe().encode_to_vec());
+
+        // A form or a `no-cors` fetch can post plain text cross origin without a preflight
+        let mut headers = origin_headers();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let response = post(&router, headers, call.clone()).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = post(&router, origin_headers(), call.clone()).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let mut headers = origin_headers();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("Application/X-WebTonic+Proto; charset=binary"),
+        );
+        let response = post(&router, headers, call).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn http_undecodable_call() {
+        let router = Arc::new(Server::builder().http_fallback().router());
+
+        let response = post(&router, call_headers(), Bytes::from_static(b"\xff\xff")).await;
+        assert_eq!(
+            grpc_status(response).await,
+            Some(tonic::Code::InvalidArgument as i32)
+        );
+    }
+
+    #[tokio::test]
+    async fn http_fallback_disabled() {
+        let router = Arc::new(Server::builder().router());
+
+        let call = Call::probe().encode_to_vec();
+        let response = http_call(
+            client(),
+            HeaderMap::new(),
+            None,
+            call.into(),
+            router.clone(),
+        );
+        assert!(response.await.is_err());
+        assert!(http_preflight(origin_headers(), router).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn http_preflight_without_credentials() {
+        let router = Arc::new(Server::builder().http_fallback().router());
+
+        let response = http_preflight(origin_headers(), router).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_METHODS], "POST");
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], ORIGIN);
+        assert!(!response
+            .headers()
+            .contains_key(ACCESS_CONTROL_ALLOW_CREDENTIALS));
+    }
+
+    #[tokio::test]
+    async fn http_credentials_for_allowed_origins() {
+        let router = Arc::new(
+            Server::builder()
+                .http_fallback()
+                .origin_policy(OriginPolicy::allow_list(vec![ORIGIN]))
+                .router(),
+        );
+
+        let response = http_preflight(origin_headers(), router.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        let response = probe(&router).await;
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("origin", HeaderValue::from_static("https://evil.com"));
+        let response = http_preflight(headers, router).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn http_call_rate() {
+        let router = Arc::new(Server::builder().http_fallback().call_rate(1, 1).router());
+
+        let response = probe(&router).await;
+        assert_eq!(grpc_status(response).await, Some(tonic::Code::Ok as i32));
+        let response = probe(&router).await;
+        assert_eq!(
+            grpc_status(response).await,
+            Some(tonic::Code::ResourceExhausted as i32)
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_call_restarts_idle_timeout() {
+        let idle = Duration::from_millis(300);
+        let mut ws = connect(Server::builder().idle_timeout(idle).router()).await;
+
+        tokio::time::sleep(idle / 2).await;
+        let reply = call(&mut ws, Call::probe()).await;
+        assert_eq!(
+            reply.grpc_status(),
+            Some(tonic::Code::InvalidArgument as i32)
+        );
+
+        let failed = Instant::now();
+        ws.recv_closed().await.unwrap();
+        assert!(failed.elapsed() >= idle - Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn panicking_handler() {
+        let panics = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = panics.clone();
+        let router = Server::builder()
+            .on_panic(move |_, path, message| {
+                let panic = (path.to_string(), message.to_string());
+                reported.lock().unwrap().push(panic);
+            })
+            .router()
+            .add_method(
+                "/test.Panic/Call",
+                Handler(|_: Request<BoxBody>| -> Response<BoxBody> { panic!("handler failed") }),
+            )
+            .add_method("/test.Echo/Call", Handler(echo));
+        let mut ws = connect(router).await;
+
+        let reply = call(&mut ws, unary("/test.Panic/Call", b"").await).await;
+        assert_eq!(reply.grpc_status(), Some(tonic::Code::Internal as i32));
+        assert_eq!(
+            *panics.lock().unwrap(),
+            [("/test.Panic/Call".to_string(), "handler failed".to_string())]
+        );
+
+        // The connection survives the panic
+        let reply = call(&mut ws, unary("/test.Echo/Call", b"hello").await).await;
+        assert_eq!(reply.grpc_status(), Some(tonic::Code::Ok as i32));
+        assert_eq!(message(reply).await, "hello");
+    }
+
+    /// The identity of an authenticated client.
+    #[derive(Clone)]
+    struct User(String);
+
+    /// Creates a router, that authenticates clients by the token in their query.
+    fn authenticated() -> Arc<Router> {
+        let user = |request: Request<BoxBody>| -> Response<BoxBody> {
+            let user = request.extensions().get::<User>().unwrap();
+            Response::builder()
+                .header("grpc-status", "0")
+                .header("user", user.0.as_str())
+                .body(tonic::body::empty_body())
+                .unwrap()
+        };
+
+        let router = Server::builder()
+            .http_fallback()
+            .authenticate(|info: ConnectionInfo| async move {
+                let token = info.query()?.strip_prefix("token=")?.to_string();
+                Some(User(token))
+            })
+            .router()
+            .add_method("/test.User/Get", Handler(user));
+        Arc::new(router)
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_clients_are_rejected() {
+        let response = warp::test::request()
+            .header("origin", ORIGIN)
+            .header("connection", "upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .reply(&filter(authenticated()))
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let call = Call::probe().encode_to_vec();
+        let response = post(&authenticated(), call_headers(), call.into()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn identity_reaches_the_handler() {
+        let mut ws = warp::test::ws()
+            .path("/?token=alice")
+            .header("origin", ORIGIN)
+            .handshake(filter(authenticated()))
+            .await
+            .unwrap();
+
+        let reply = call(&mut ws, unary("/test.User/Get", b"").await).await;
+        assert_eq!(reply.headers()["user"], "alice");
+    }
+
+    #[tokio::test]
+    async fn connection_info_reaches_the_handler() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let record = seen.clone();
+        let router = Server::builder().router().add_method(
+            "/test.Info/Get",
+            Handler(move |request: Request<BoxBody>| -> Response<BoxBody> {
+                let info = request.extensions().get::<ConnectionInfo>().unwrap();
+                record.lock().unwrap().push(info.clone());
+                echo(request)
+            }),
+        );
+        let mut ws = warp::test::ws()
+            .path("/?room=1")
+            .header("origin", ORIGIN)
+            .header("sec-websocket-protocol", "webtonic")
+            .handshake(filter(Arc::new(router)))
+            .await
+            .unwrap();
+
+        call(&mut ws, unary("/test.Info/Get", b"").await).await;
+        call(&mut ws, unary("/test.Info/Get", b"").await).await;
+
+        let seen = seen.lock().unwrap();
+        let info = &seen[0];
+        assert!(info.remote_addr().unwrap().ip().is_loopback());
+        assert_eq!(info.query(), Some("room=1"));
+        assert_eq!(info.headers()["sec-websocket-protocol"], "webtonic");
+        assert_eq!(info.headers()["origin"], ORIGIN);
+        assert_eq!(seen[1].id(), info.id());
+    }
+
+    /// A service of a tonic router, that answers every call with its own message.
+    #[derive(Clone)]
+    struct TonicEcho;
+
+    impl NamedService for TonicEcho {
+        const NAME: &'static str = "test.Echo";
+    }
+
+    impl Service<Request<hyper::Body>> for TonicEcho {
+        type Response = Response<BoxBody>;
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut core::task::Context<'_>,
+        ) -> core::task::Poll<Result<(), Self::Error>> {
+            Ok(()).into()
+        }
+
+        fn call(&mut self, request: Request<hyper::Body>) -> Self::Future {
+            use http_body::Body as _;
+
+            let body = request
+                .into_body()
+                .map_err(|e| Status::internal(e.to_string()));
+            futures::future::ok(
+                Response::builder()
+                    .header("grpc-status", "0")
+                    .body(BoxBody::new(body))
+                    .unwrap(),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn tonic_router() {
+        let routes = tonic::transport::Server::builder()
+            .add_service(TonicEcho)
+            .into_service();
+        let router = Server::builder().add_routes(TonicRouter::new(routes));
+        let mut ws = connect(router).await;
+
+        let reply = call(&mut ws, unary("/test.Echo/Call", b"hello").await).await;
+        assert_eq!(reply.grpc_status(), Some(tonic::Code::Ok as i32));
+        assert_eq!(message(reply).await, "hello");
+
+        // Calls to services, that the tonic router does not know, are answered by it as well
+        let reply = call(&mut ws, unary("/test.Unknown/Call", b"").await).await;
+        assert_eq!(reply.grpc_status(), Some(tonic::Code::Unimplemented as i32));
+    }
+
+    #[cfg(feature = "health")]
+    #[tokio::test]
+    async fn health_status() {
+        use tonic_health::proto::{
+            health_check_response::ServingStatus as CheckStatus, HealthCheckRequest,
+            HealthCheckResponse,
+        };
+
+        let (health, health_service) = health::health_reporter();
+        health
+            .set_service_status("test.Echo", health::ServingStatus::Serving)
+            .await;
+        let router = Server::builder()
+            .healthz(health.clone())
+            .add_service(health_service);
+        let filter = filter(Arc::new(router.clone()));
+        let mut ws = connect(router).await;
+
+        let check = HealthCheckRequest {
+            service: "test.Echo".to_string(),
+        }
+        .encode_to_vec();
+        let reply = call(&mut ws, unary("/grpc.health.v1.Health/Check", &check).await).await;
+        let response = HealthCheckResponse::decode(message(reply).await).unwrap();
+        assert_eq!(response.status, CheckStatus::Serving as i32);
+        let response = warp::test::request().path("/healthz").reply(&filter).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Changes are visible to both the service and the HTTP endpoint
+        health
+            .set_service_status("test.Echo", health::ServingStatus::NotServing)
+            .await;
+        let reply = call(&mut ws, unary("/grpc.health.v1.Health/Check", &check).await).await;
+        let response = HealthCheckResponse::decode(message(reply).await).unwrap();
+        assert_eq!(response.status, CheckStatus::NotServing as i32);
+        let response = warp::test::request().path("/healthz").reply(&filter).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let response = warp::test::request()
+            .path("/healthz?service=test.Unknown")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn metrics_are_recorded() {
+        let router = Server::builder()
+            .metrics(metrics::Metrics::new(), "/metrics")
+            .router()
+            .add_method("/test.Echo/Call", Handler(echo));
+        let filter = filter(Arc::new(router.clone()));
+        let mut ws = connect(router).await;
+
+        call(&mut ws, unary("/test.Echo/Call", b"hello").await).await;
+        call(&mut ws, unary("/test.Unknown/Call", b"").await).await;
+
+        let response = warp::test::request().path("/metrics").reply(&filter).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert!(body.contains("webtonic_connections_active 1"));
+        assert!(
+            body.contains(r#"webtonic_calls_total{code="0",method="Call",service="test.Echo"} 1"#)
+        );
+        assert!(body.contains(
+            r#"webtonic_calls_total{code="12",method="unimplemented",service="unimplemented"} 1"#
+        ));
+        assert!(!body.contains("test.Unknown"));
+
+        let response = warp::test::request().path("/other").reply(&filter).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// The recorded fields of a span, formatted with `Debug`.
+    #[cfg(feature = "tracing")]
+    type SpanFields = Vec<(String, String)>;
+
+    /// A subscriber, that records the names and fields of all spans.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct Spans(Arc<std::sync::Mutex<Vec<(&'static str, SpanFields)>>>);
+
+    #[cfg(feature = "tracing")]
+    impl Spans {
+        /// Returns the fields of the first span named `name`.
+        fn fields(&self, name: &str) -> SpanFields {
+            let spans = self.0.lock().unwrap();
+            let (_, fields) = spans.iter().find(|(span, _)| *span == name).unwrap();
+            fields.clone()
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    struct Fields<'a>(&'a mut SpanFields);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for Spans {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut spans = self.0.lock().unwrap();
+            let mut fields = Vec::new();
+            span.record(&mut Fields(&mut fields));
+            spans.push((span.metadata().name(), fields));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            let (_, fields) = &mut spans[span.into_u64() as usize - 1];
+            values.record(&mut Fields(fields));
+        }
+
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+        fn event(&self, _event: &tracing::Event<'_>) {}
+
+        fn enter(&self, _span: &tracing::span::Id) {}
+
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn call_spans() {
+        let spans = Spans::default();
+        let _subscriber = tracing::subscriber::set_default(spans.clone());
+
+        let router = Server::builder()
+            .router()
+            .add_method("/test.Echo/Call", Handler(echo));
+        let mut ws = connect(router).await;
+        call(&mut ws, unary("/test.Echo/Call", b"hello").await).await;
+
+        let connection = spans.fields("connection");
+        assert!(connection.iter().any(|(name, _)| name == "connection.id"));
+        let call = spans.fields("call");
+        for field in [
+            ("rpc.service", "\"test.Echo\""),
+            ("rpc.method", "\"Call\""),
+            ("rpc.grpc.status_code", "0"),
+        ] {
+            assert!(
+                call.contains(&(field.0.to_string(), field.1.to_string())),
+                "{:?} not in {:?}",
+                field,
+                call
+            );
+        }
+        assert!(call.iter().any(|(name, _)| name == "duration_ms"));
+    }
+
+    /// The parts of `grpc.reflection.v1alpha.ServerReflectionRequest`, that list the services.
+    #[cfg(feature = "reflection")]
+    #[derive(Clone, PartialEq, ProstMessage)]
+    struct ListServicesRequest {
+        #[prost(string, tag = "7")]
+        list_services: String,
+    }
+
+    /// The parts of `grpc.reflection.v1alpha.ServerReflectionResponse`, that list the services.
+    #[cfg(feature = "reflection")]
+    #[derive(Clone, PartialEq, ProstMessage)]
+    struct ListServicesResponse {
+        #[prost(message, optional, tag = "6")]
+        list_services_response: Option<ServiceList>,
+    }
+
+    #[cfg(feature = "reflection")]
+    #[derive(Clone, PartialEq, ProstMessage)]
+    struct ServiceList {
+        #[prost(message, repeated, tag = "1")]
+        service: Vec<ServiceName>,
+    }
+
+    #[cfg(feature = "reflection")]
+    #[derive(Clone, PartialEq, ProstMessage)]
+    struct ServiceName {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    #[cfg(feature = "reflection")]
+    #[tokio::test]
+    async fn reflection_over_the_tunnel() {
+        let reflection = reflection::Builder::configure().build().unwrap();
+        let router = Server::builder().add_service(reflection);
+        let mut ws = connect(router).await;
+
+        let request = ListServicesRequest {
+            list_services: "*".to_string(),
+        }
+        .encode_to_vec();
+        let path = "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo";
+        let reply = call(&mut ws, unary(path, &request).await).await;
+        assert_eq!(reply.grpc_status(), Some(tonic::Code::Ok as i32));
+
+        let response = ListServicesResponse::decode(message(reply).await).unwrap();
+        let services: Vec<_> = response
+            .list_services_response
+            .unwrap()
+            .service
+            .into_iter()
+            .map(|service| service.name)
+            .collect();
+        assert_eq!(services, ["grpc.reflection.v1alpha.ServerReflection"]);
+    }
+}
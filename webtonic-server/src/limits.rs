@@ -0,0 +1,363 @@
+use core::time::Duration;
+use http::header::HeaderMap;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::time::Instant;
+
+/// The limits, that can be exceeded by a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Limit {
+    /// The maximum number of connections to the server.
+    Connections,
+
+    /// The maximum number of connections from a single IP address.
+    ConnectionsPerIp,
+
+    /// The maximum rate of calls on a connection.
+    CallRate,
+
+    /// The maximum number or size of the headers of a call.
+    Headers,
+}
+
+impl Limit {
+    /// The name of the limit, as used in logs and metrics.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Limit::Connections => "connections",
+            Limit::ConnectionsPerIp => "connections_per_ip",
+            Limit::CallRate => "call_rate",
+            Limit::Headers => "headers",
+        }
+    }
+}
+
+/// The resource limits of a [`Server`](crate::Server).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Limits {
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) max_connections_per_ip: Option<usize>,
+    pub(crate) call_rate: Option<(u32, u32)>,
+    pub(crate) max_header_count: Option<usize>,
+    pub(crate) max_header_bytes: Option<usize>,
+    connections: Arc<Mutex<Connections>>,
+
+    /// The call rate of the clients, that make their calls over HTTP.
+    http_calls: Arc<Mutex<HashMap<Option<IpAddr>, TokenBucket>>>,
+}
+
+/// The number of clients, whose HTTP call rate is tracked.
+/// Once reached, the clients, that were idle the longest, are forgotten.
+const MAX_TRACKED_HTTP_CLIENTS: usize = 4096;
+
+#[derive(Debug, Default)]
+struct Connections {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl Limits {
+    /// Reserves a connection slot for a client.
+    ///
+    /// # Returns
+    /// - A [`ConnectionPermit`](ConnectionPermit), that frees the slot when dropped.
+    /// - The exceeded [`Limit`](Limit), if there is no free slot.
+    pub(crate) fn acquire(&self, ip: Option<IpAddr>) -> Result<ConnectionPermit, Limit> {
+        let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+
+        if matches!(self.max_connections, Some(max) if connections.total >= max) {
+            return Err(Limit::Connections);
+        }
+        if let Some(ip) = ip {
+            let count = connections.per_ip.get(&ip).copied().unwrap_or_default();
+            if matches!(self.max_connections_per_ip, Some(max) if count >= max) {
+                return Err(Limit::ConnectionsPerIp);
+            }
+            connections.per_ip.insert(ip, count + 1);
+        }
+        connections.total += 1;
+
+        Ok(ConnectionPermit {
+            connections: self.connections.clone(),
+            ip,
+        })
+    }
+
+    /// Creates the [`TokenBucket`](TokenBucket) of a new connection, if the call rate is limited.
+    pub(crate) fn call_limiter(&self) -> Option<TokenBucket> {
+        self.call_rate
+            .map(|(rate, burst)| TokenBucket::new(rate, burst))
+    }
+
+    /// Takes a token from the [`TokenBucket`](TokenBucket) of `ip`, if the call rate is limited.
+    ///
+    /// Calls over HTTP are not made over a connection, so their rate is limited per IP address.
+    ///
+    /// # Returns
+    /// - `true`, if the call may proceed
+    /// - `false`, if the client exceeded the call rate
+    pub(crate) fn try_acquire_http_call(&self, ip: Option<IpAddr>) -> bool {
+        let (rate, burst) = match self.call_rate {
+            Some(call_rate) => call_rate,
+            None => return true,
+        };
+        let mut buckets = self.http_calls.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_TRACKED_HTTP_CLIENTS && !buckets.contains_key(&ip) {
+            // A full bucket is the same as a new one, so it does not need to be kept
+            buckets.retain(|_, bucket| !bucket.is_full());
+
+            // Clients, that rotate their address, keep their buckets from refilling
+            if buckets.len() >= MAX_TRACKED_HTTP_CLIENTS {
+                let least_recent = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.refilled)
+                    .map(|(ip, _)| *ip);
+                if let Some(least_recent) = least_recent {
+                    buckets.remove(&least_recent);
+                }
+            }
+        }
+
+        buckets
+            .entry(ip)
+            .or_insert_with(|| TokenBucket::new(rate, burst))
+            .try_acquire()
+    }
+
+    /// Checks the headers of a call against the limits.
+    pub(crate) fn check_headers(&self, headers: &HeaderMap) -> Result<(), Limit> {
+        if matches!(self.max_header_count, Some(max) if headers.len() > max) {
+            return Err(Limit::Headers);
+        }
+
+        if let Some(max) = self.max_header_bytes {
+            let bytes: usize = headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len())
+                .sum();
+            if bytes > max {
+                return Err(Limit::Headers);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Holds a connection slot, until it is dropped.
+#[derive(Debug)]
+pub(crate) struct ConnectionPermit {
+    connections: Arc<Mutex<Connections>>,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+        connections.total -= 1;
+
+        if let Some(ip) = self.ip {
+            match connections.per_ip.get_mut(&ip) {
+                Some(count) if *count > 1 => *count -= 1,
+                _ => {
+                    connections.per_ip.remove(&ip);
+                }
+            }
+        }
+    }
+}
+
+/// The rounding error, that is tolerated when taking a token.
+const TOKEN_EPSILON: f64 = 1e-9;
+
+/// Limits the rate of calls on a single connection.
+///
+/// The bucket holds up to `burst` tokens and is refilled with `rate` tokens per second.
+/// Every call takes one token.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, burst: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Takes a token from the bucket.
+    ///
+    /// # Returns
+    /// - `true`, if the call may proceed
+    /// - `false`, if the bucket is empty
+    pub(crate) fn try_acquire(&mut self) -> bool {
+        self.refill();
+
+        // Refills in small steps may add up to slightly less than a whole token
+        if self.tokens >= 1.0 - TOKEN_EPSILON {
+            self.tokens = (self.tokens - 1.0).max(0.0);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns `true`, if the bucket was refilled completely.
+    fn is_full(&self) -> bool {
+        let elapsed: Duration = Instant::now() - self.refilled;
+        self.tokens + elapsed.as_secs_f64() * self.rate >= self.burst - TOKEN_EPSILON
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed: Duration = now - self.refilled;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        self.refilled = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time;
+
+    #[tokio::test(start_paused = true)]
+    async fn burst() {
+        let mut bucket = TokenBucket::new(1, 3);
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refill() {
+        let mut bucket = TokenBucket::new(10, 1);
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+
+        // One token is refilled every 100ms
+        time::advance(Duration::from_millis(90)).await;
+        assert!(!bucket.try_acquire());
+        time::advance(Duration::from_millis(10)).await;
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refill_is_capped_at_burst() {
+        let mut bucket = TokenBucket::new(100, 2);
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+
+        time::advance(Duration::from_secs(60)).await;
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_burst_rejects_all() {
+        let mut bucket = TokenBucket::new(100, 0);
+        time::advance(Duration::from_secs(1)).await;
+        assert!(!bucket.try_acquire());
+    }
+
+    #[test]
+    fn connection_slots() {
+        let limits = Limits {
+            max_connections: Some(2),
+            max_connections_per_ip: Some(1),
+            ..Limits::default()
+        };
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        let other = IpAddr::from([127, 0, 0, 2]);
+
+        let permit = limits.acquire(Some(ip)).unwrap();
+        assert_eq!(
+            limits.acquire(Some(ip)).unwrap_err(),
+            Limit::ConnectionsPerIp
+        );
+        let _other = limits.acquire(Some(other)).unwrap();
+        assert_eq!(limits.acquire(None).unwrap_err(), Limit::Connections);
+
+        // Dropping the permit frees the slot
+        drop(permit);
+        assert!(limits.acquire(Some(ip)).is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn http_call_rate_per_ip() {
+        let limits = Limits {
+            call_rate: Some((1, 1)),
+            ..Limits::default()
+        };
+        let ip = Some(IpAddr::from([127, 0, 0, 1]));
+        let other = Some(IpAddr::from([127, 0, 0, 2]));
+
+        assert!(limits.try_acquire_http_call(ip));
+        assert!(!limits.try_acquire_http_call(ip));
+        assert!(limits.try_acquire_http_call(other));
+
+        time::advance(Duration::from_secs(1)).await;
+        assert!(limits.try_acquire_http_call(ip));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_http_clients_are_forgotten() {
+        let limits = Limits {
+            call_rate: Some((1, 1)),
+            ..Limits::default()
+        };
+        for i in 0..MAX_TRACKED_HTTP_CLIENTS as u32 {
+            assert!(limits.try_acquire_http_call(Some(IpAddr::from(i.to_be_bytes()))));
+        }
+
+        time::advance(Duration::from_secs(1)).await;
+        assert!(limits.try_acquire_http_call(None));
+        assert_eq!(limits.http_calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn least_recent_http_clients_are_forgotten() {
+        let limits = Limits {
+            call_rate: Some((1, 1)),
+            ..Limits::default()
+        };
+        let ip = |i: u32| Some(IpAddr::from(i.to_be_bytes()));
+        assert!(limits.try_acquire_http_call(ip(0)));
+        time::advance(Duration::from_millis(1)).await;
+        for i in 1..MAX_TRACKED_HTTP_CLIENTS as u32 {
+            assert!(limits.try_acquire_http_call(ip(i)));
+        }
+
+        // None of the buckets refilled, so the oldest one makes room for the new client
+        let tracked = MAX_TRACKED_HTTP_CLIENTS as u32;
+        assert!(limits.try_acquire_http_call(ip(tracked)));
+        {
+            let buckets = limits.http_calls.lock().unwrap();
+            assert_eq!(buckets.len(), MAX_TRACKED_HTTP_CLIENTS);
+            assert!(!buckets.contains_key(&ip(0)));
+            assert!(buckets.contains_key(&ip(tracked)));
+        }
+
+        for i in tracked + 1..tracked + 100 {
+            assert!(limits.try_acquire_http_call(ip(i)));
+        }
+        assert_eq!(
+            limits.http_calls.lock().unwrap().len(),
+            MAX_TRACKED_HTTP_CLIENTS
+        );
+    }
+}
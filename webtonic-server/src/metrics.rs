@@ -0,0 +1,211 @@
+//! Prometheus metrics of the websocket connections and the calls tunneled through them.
+//!
+//! The metrics are exposed in the Prometheus text format on an HTTP path,
+//! that is served next to the tunnel (see [`Server::metrics`](crate::Server::metrics)).
+//!
+//! # Example
+//! ```ignore
+//! webtonic_server::Server::builder()
+//!     .metrics(Metrics::new(), "/metrics")
+//!     .add_service(GreeterServer::new(greeter))
+//!     .serve(([127, 0, 0, 1], 8080))
+//!     .await;
+//! ```
+
+use core::time::Duration;
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+
+use crate::{limits::Limit, OverflowPolicy};
+
+/// The metrics collected by a [`Server`](crate::Server).
+///
+/// The following metrics are recorded:
+/// - `webtonic_connections_active`: the number of open websocket connections
+/// - `webtonic_calls_total`: the number of calls, by `service`, `method` and gRPC `code`.
+///   Calls, that fail with `UNIMPLEMENTED`, are counted under the service and method
+///   `unimplemented`
+/// - `webtonic_call_duration_seconds`: the latency of the calls, by `service` and `method`
+/// - `webtonic_request_bytes`: the size of the received calls, by `service` and `method`
+/// - `webtonic_response_bytes`: the size of the sent replies, by `service` and `method`
+/// - `webtonic_send_buffer_full_total`: the number of times the send buffer of a connection
+///   ran full, by the applied `policy`
+/// - `webtonic_limit_exceeded_total`: the number of rejected connections and calls,
+///   by the exceeded `limit`
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    connections: IntGauge,
+    calls: IntCounterVec,
+    duration: HistogramVec,
+    request_bytes: HistogramVec,
+    response_bytes: HistogramVec,
+    send_buffer_full: IntCounterVec,
+    limit_exceeded: IntCounterVec,
+}
+
+impl Metrics {
+    /// Creates the metrics in a new [`Registry`](prometheus::Registry).
+    pub fn new() -> Self {
+        Self::with_registry(Registry::new()).expect("metrics registered in a fresh registry")
+    }
+
+    /// Creates the metrics in an existing [`Registry`](prometheus::Registry).
+    ///
+    /// This allows to expose the metrics of the server together with the metrics
+    /// of the application.
+    ///
+    /// # Returns
+    /// - The [`Metrics`](Metrics) on success.
+    /// - An error, if the registry already contains metrics of the same name.
+    pub fn with_registry(registry: Registry) -> Result<Self, prometheus::Error> {
+        let connections = IntGauge::new(
+            "webtonic_connections_active",
+            "Number of open websocket connections",
+        )?;
+        let calls = IntCounterVec::new(
+            Opts::new("webtonic_calls_total", "Number of tunneled calls"),
+            &["service", "method", "code"],
+        )?;
+        let duration = HistogramVec::new(
+            HistogramOpts::new(
+                "webtonic_call_duration_seconds",
+                "Latency of the tunneled calls",
+            ),
+            &["service", "method"],
+        )?;
+        let request_bytes = HistogramVec::new(
+            HistogramOpts::new("webtonic_request_bytes", "Size of the received calls")
+                .buckets(exponential_buckets(64.0, 4.0, 10)?),
+            &["service", "method"],
+        )?;
+        let response_bytes = HistogramVec::new(
+            HistogramOpts::new("webtonic_response_bytes", "Size of the sent replies")
+                .buckets(exponential_buckets(64.0, 4.0, 10)?),
+            &["service", "method"],
+        )?;
+        let send_buffer_full = IntCounterVec::new(
+            Opts::new(
+                "webtonic_send_buffer_full_total",
+                "Number of times the send buffer of a connection ran full",
+            ),
+            &["policy"],
+        )?;
+        let limit_exceeded = IntCounterVec::new(
+            Opts::new(
+                "webtonic_limit_exceeded_total",
+                "Number of connections and calls rejected by a limit",
+            ),
+            &["limit"],
+        )?;
+
+        registry.register(Box::new(connections.clone()))?;
+        registry.register(Box::new(calls.clone()))?;
+        registry.register(Box::new(duration.clone()))?;
+        registry.register(Box::new(request_bytes.clone()))?;
+        registry.register(Box::new(response_bytes.clone()))?;
+        registry.register(Box::new(send_buffer_full.clone()))?;
+        registry.register(Box::new(limit_exceeded.clone()))?;
+
+        Ok(Self {
+            registry,
+            connections,
+            calls,
+            duration,
+            request_bytes,
+            response_bytes,
+            send_buffer_full,
+            limit_exceeded,
+        })
+    }
+
+    /// Returns the [`Registry`](prometheus::Registry) the metrics are registered in.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Counts a connection as active, until the returned guard is dropped.
+    pub(crate) fn connection(&self) -> ConnectionGuard {
+        self.connections.inc();
+        ConnectionGuard(self.connections.clone())
+    }
+
+    /// Records a finished call.
+    pub(crate) fn record_call(&self, call: &CallRecord<'_>) {
+        // Do not create new label values for calls to services that do not exist,
+        // since any client could send arbitrary paths
+        let (service, method) = if call.code == tonic::Code::Unimplemented as i32 {
+            ("unimplemented", "unimplemented")
+        } else {
+            (call.service, call.method)
+        };
+
+        self.calls
+            .with_label_values(&[service, method, &call.code.to_string()])
+            .inc();
+        self.duration
+            .with_label_values(&[service, method])
+            .observe(call.duration.as_secs_f64());
+        self.request_bytes
+            .with_label_values(&[service, method])
+            .observe(call.request_bytes as f64);
+        self.response_bytes
+            .with_label_values(&[service, method])
+            .observe(call.response_bytes as f64);
+    }
+
+    /// Records, that the send buffer of a connection ran full.
+    pub(crate) fn send_buffer_full(&self, policy: OverflowPolicy) {
+        let policy = match policy {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::Disconnect => "disconnect",
+        };
+        self.send_buffer_full.with_label_values(&[policy]).inc();
+    }
+
+    /// Records, that a connection or call was rejected by a limit.
+    pub(crate) fn limit_exceeded(&self, limit: Limit) {
+        self.limit_exceeded
+            .with_label_values(&[limit.as_str()])
+            .inc();
+    }
+
+    /// Encodes the metrics in the Prometheus text format.
+    pub(crate) fn encode(&self) -> String {
+        let mut buffer = vec![];
+        let encoder = TextEncoder::new();
+        match encoder.encode(&self.registry.gather(), &mut buffer) {
+            Ok(()) => (),
+            Err(e) => log::warn!("failed to encode metrics {:?}", e),
+        }
+
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decrements the number of active connections, when dropped.
+pub(crate) struct ConnectionGuard(IntGauge);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// The observations made about a single call.
+pub(crate) struct CallRecord<'a> {
+    pub(crate) service: &'a str,
+    pub(crate) method: &'a str,
+    pub(crate) code: i32,
+    pub(crate) duration: Duration,
+    pub(crate) request_bytes: usize,
+    pub(crate) response_bytes: usize,
+}
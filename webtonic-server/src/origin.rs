@@ -0,0 +1,206 @@
+use core::fmt;
+use std::sync::Arc;
+
+/// Decides, from which origins browsers may open websocket connections to the [`Server`](crate::Server).
+///
+/// Browsers send cookies along with websocket upgrade requests, regardless of which site opened
+/// the connection.
+/// If the server relies on cookies for authentication, any malicious site could therefore open a
+/// connection in the name of the user (cross-site websocket hijacking).
+/// Restricting the allowed origins prevents that.
+///
+/// Upgrade requests without an `Origin` header do not originate from a browser and are always
+/// allowed.
+///
+/// # Example
+/// ```ignore
+/// webtonic_server::Server::builder()
+///     .origin_policy(OriginPolicy::allow_list(vec![
+///         "https://example.com",
+///         "https://*.example.com",
+///     ]))
+///     .add_service(GreeterServer::new(greeter))
+///     .serve(([127, 0, 0, 1], 8080))
+///     .await;
+/// ```
+#[derive(Clone)]
+pub struct OriginPolicy(Policy);
+
+#[derive(Clone)]
+enum Policy {
+    Any,
+    AllowList(Vec<String>),
+    Predicate(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl OriginPolicy {
+    /// Allow connections from any origin.
+    ///
+    /// This is the default policy.
+    pub fn any() -> Self {
+        Self(Policy::Any)
+    }
+
+    /// Allow connections only from the listed origins.
+    ///
+    /// An origin of the form `https://*.example.com` allows all subdomains of `example.com`,
+    /// but not `example.com` itself.
+    ///
+    /// # Arguments
+    /// - `origins`: The allowed origins, e.g. `https://example.com`.
+    pub fn allow_list<I, S>(origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self(Policy::AllowList(
+            origins.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    /// Allow connections from all origins, for which `f` returns `true`.
+    pub fn predicate<F>(f: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        Self(Policy::Predicate(Arc::new(f)))
+    }
+
+    /// Checks, whether a connection from `origin` is allowed.
+    pub(crate) fn allows(&self, origin: Option<&str>) -> bool {
+        let origin = match origin {
+            Some(origin) => origin,
+            None => return true,
+        };
+
+        match &self.0 {
+            Policy::Any => true,
+            Policy::AllowList(origins) => origins
+                .iter()
+                .any(|pattern| origin_matches(pattern, origin)),
+            Policy::Predicate(f) => f(origin),
+        }
+    }
+
+    /// Returns `true`, if the policy decides per origin, instead of allowing any origin.
+    ///
+    /// Browsers only send credentials, e.g. cookies, along with cross origin calls over HTTP,
+    /// if the policy restricts the origins.
+    pub(crate) fn is_restrictive(&self) -> bool {
+        !matches!(self.0, Policy::Any)
+    }
+}
+
+impl Default for OriginPolicy {
+    fn default() -> Self {
+        Self::any()
+    }
+}
+
+impl fmt::Debug for OriginPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Policy::Any => f.write_str("OriginPolicy::Any"),
+            Policy::AllowList(origins) => f
+                .debug_tuple("OriginPolicy::AllowList")
+                .field(origins)
+                .finish(),
+            Policy::Predicate(_) => f.write_str("OriginPolicy::Predicate"),
+        }
+    }
+}
+
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    match pattern.split_once("://*.") {
+        Some((scheme, domain)) => {
+            let host = match origin.split_once("://") {
+                Some((origin_scheme, host)) if origin_scheme.eq_ignore_ascii_case(scheme) => host,
+                _ => return false,
+            };
+
+            match host.len().checked_sub(domain.len() + 1) {
+                Some(0) | None => false,
+                Some(split) => {
+                    host.as_bytes()[split] == b'.' && host[split + 1..].eq_ignore_ascii_case(domain)
+                }
+            }
+        }
+        None => pattern.eq_ignore_ascii_case(origin),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_origin() {
+        assert!(origin_matches("https://example.com", "https://example.com"));
+        assert!(origin_matches("https://example.com", "HTTPS://Example.COM"));
+        assert!(!origin_matches(
+            "https://example.com",
+            "https://example.org"
+        ));
+    }
+
+    #[test]
+    fn wildcard_subdomain() {
+        let pattern = "https://*.example.com";
+        assert!(origin_matches(pattern, "https://app.example.com"));
+        assert!(origin_matches(pattern, "https://a.b.example.com"));
+        assert!(origin_matches(pattern, "https://APP.Example.com"));
+
+        // The wildcard does not match the domain itself or lookalike domains
+        assert!(!origin_matches(pattern, "https://example.com"));
+        assert!(!origin_matches(pattern, "https://.example.com"));
+        assert!(!origin_matches(pattern, "https://evilexample.com"));
+        assert!(!origin_matches(pattern, "https://example.com.evil.org"));
+    }
+
+    #[test]
+    fn scheme_mismatch() {
+        assert!(!origin_matches("https://example.com", "http://example.com"));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "http://app.example.com"
+        ));
+        assert!(!origin_matches("https://*.example.com", "app.example.com"));
+    }
+
+    #[test]
+    fn port_mismatch() {
+        assert!(origin_matches(
+            "https://example.com:8443",
+            "https://example.com:8443"
+        ));
+        assert!(!origin_matches(
+            "https://example.com",
+            "https://example.com:8443"
+        ));
+        assert!(!origin_matches(
+            "https://example.com:8443",
+            "https://example.com"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://app.example.com:8443"
+        ));
+    }
+
+    #[test]
+    fn null_origin() {
+        // Sandboxed frames and local files send the literal origin `null`
+        let policy = OriginPolicy::allow_list(vec!["https://example.com", "https://*.example.com"]);
+        assert!(!policy.allows(Some("null")));
+        assert!(!policy.allows(Some("")));
+        assert!(OriginPolicy::any().allows(Some("null")));
+    }
+
+    #[test]
+    fn missing_origin() {
+        let policy = OriginPolicy::allow_list(vec!["https://example.com"]);
+        assert!(policy.allows(None));
+        assert!(OriginPolicy::predicate(|_| false).allows(None));
+        assert!(!OriginPolicy::predicate(|_| false).allows(Some("https://example.com")));
+    }
+}
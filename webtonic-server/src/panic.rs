@@ -0,0 +1,41 @@
+use core::{any::Any, fmt};
+use std::sync::Arc;
+
+use crate::ConnectionInfo;
+
+type PanicCallback = dyn Fn(&ConnectionInfo, &str, &str) + Send + Sync;
+
+/// The callback of a [`Server`](crate::Server), that is informed about panicking handlers.
+#[derive(Clone)]
+pub(crate) struct PanicHook(Arc<PanicCallback>);
+
+impl PanicHook {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&ConnectionInfo, &str, &str) + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    /// Reports a panic, that occured while handling a call to `path`.
+    pub(crate) fn report(&self, info: &ConnectionInfo, path: &str, message: &str) {
+        (self.0)(info, path, message)
+    }
+}
+
+impl fmt::Debug for PanicHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PanicHook")
+    }
+}
+
+/// Extracts the message from the payload of a panic.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
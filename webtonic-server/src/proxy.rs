@@ -0,0 +1,195 @@
+use core::task::{Context, Poll};
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt,
+};
+use http::{header::HeaderValue, request::Request, response::Response};
+use http_body::Body as HttpBody;
+use tonic::{
+    body::BoxBody,
+    codegen::Never,
+    transport::{Channel, Endpoint},
+    Status,
+};
+use tower_service::Service;
+
+use crate::{adapter::to_status, ConnectionInfo};
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Forwards the calls to an upstream gRPC server.
+///
+/// This allows to run the `WebTonic` server as a browser facing gateway in front of existing
+/// gRPC servers, without linking the services into the same binary.
+///
+/// The call is forwarded as is, including its metadata and deadline.
+/// The headers, body and trailers of the upstream response are sent back to the client
+/// unchanged.
+/// The `x-forwarded-for` header of the call is replaced by the address of the client, since
+/// the client could claim any address in it.
+/// If the server runs behind a reverse proxy, the `x-forwarded-for` header, that the proxy set
+/// on the upgrade request, can be trusted instead (see
+/// [`ProxyService::trust_forwarded_for`](ProxyService::trust_forwarded_for)).
+///
+/// If the upstream can not be reached, the call fails with `UNAVAILABLE`.
+///
+/// # Example
+/// ```ignore
+/// let greeter = Channel::from_static("http://[::1]:50051").connect_lazy();
+/// let echo = Channel::from_static("http://[::1]:50052").connect_lazy();
+///
+/// webtonic_server::Server::builder()
+///     .router()
+///     .add_named_service("helloworld.Greeter", ProxyService::new(greeter))
+///     .add_named_service("echo.Echo", ProxyService::new(echo))
+///     .serve(([127, 0, 0, 1], 8080))
+///     .await;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProxyService {
+    channel: Channel,
+    trust_forwarded_for: bool,
+}
+
+impl ProxyService {
+    /// Creates a [`ProxyService`](ProxyService), that forwards the calls over `channel`.
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            channel,
+            trust_forwarded_for: false,
+        }
+    }
+
+    /// Creates a [`ProxyService`](ProxyService), that lazily connects to `endpoint`,
+    /// once the first call is forwarded.
+    pub fn lazy(endpoint: Endpoint) -> Self {
+        Self::new(endpoint.connect_lazy())
+    }
+
+    /// Trust the `x-forwarded-for` header of the upgrade request and append the address of the
+    /// client to it.
+    ///
+    /// **Note**: Only enable this, if all connections pass through a reverse proxy, that sets
+    /// the header. Otherwise, clients can claim any address.
+    ///
+    /// # Returns
+    /// - The [`ProxyService`](ProxyService), that trusts the reverse proxy.
+    pub fn trust_forwarded_for(mut self) -> Self {
+        self.trust_forwarded_for = true;
+        self
+    }
+
+    /// Replaces the `x-forwarded-for` header of a call with the address of its client.
+    fn set_forwarded_for(&self, request: &mut Request<BoxBody>) {
+        // The headers of the call are chosen by the client
+        request.headers_mut().remove(FORWARDED_FOR);
+
+        let info = match request.extensions().get::<ConnectionInfo>() {
+            Some(info) => info,
+            None => return,
+        };
+        let addr = match info.remote_addr() {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        let proxied = info
+            .headers()
+            .get(FORWARDED_FOR)
+            .filter(|_| self.trust_forwarded_for)
+            .and_then(|forwarded| forwarded.to_str().ok());
+        let forwarded = match proxied {
+            Some(proxied) => format!("{}, {}", proxied, addr.ip()),
+            None => addr.ip().to_string(),
+        };
+        if let Ok(forwarded) = HeaderValue::from_str(&forwarded) {
+            request.headers_mut().insert(FORWARDED_FOR, forwarded);
+        }
+    }
+}
+
+impl Service<Request<BoxBody>> for ProxyService {
+    type Response = Response<BoxBody>;
+    type Error = Never;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Readiness of the channel is awaited per call, such that errors can be returned as status
+        Ok(()).into()
+    }
+
+    fn call(&mut self, mut request: Request<BoxBody>) -> Self::Future {
+        let mut channel = self.channel.clone();
+        self.set_forwarded_for(&mut request);
+
+        async move {
+            let response = match future::poll_fn(|cx| channel.poll_ready(cx)).await {
+                Ok(()) => channel.call(request).await,
+                Err(e) => Err(e),
+            };
+
+            Ok(match response {
+                Ok(response) => response.map(|body| BoxBody::new(body.map_err(to_status))),
+                Err(e) => {
+                    log::warn!("failed to forward call to upstream {:?}", e);
+                    Status::unavailable(format!("upstream unavailable: {}", e)).to_http()
+                }
+            })
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderMap;
+    use std::net::SocketAddr;
+    use tonic::body::empty_body;
+
+    fn proxy() -> ProxyService {
+        ProxyService::lazy(Endpoint::from_static("http://127.0.0.1:1"))
+    }
+
+    /// Creates a call, whose client claims to be `claimed` and whose upgrade request was
+    /// forwarded by a proxy for `proxied`.
+    fn request(claimed: &'static str, proxied: &'static str) -> Request<BoxBody> {
+        let mut upgrade = HeaderMap::new();
+        upgrade.insert(FORWARDED_FOR, HeaderValue::from_static(proxied));
+        let info = ConnectionInfo::new(
+            Some(SocketAddr::from(([10, 0, 0, 1], 40000))),
+            upgrade,
+            None,
+        );
+
+        let mut request = Request::builder()
+            .header(FORWARDED_FOR, claimed)
+            .body(empty_body())
+            .unwrap();
+        info.insert_into(request.extensions_mut());
+        request
+    }
+
+    #[tokio::test]
+    async fn forwarded_for_is_not_taken_from_the_client() {
+        let mut request = request("1.2.3.4", "192.0.2.1");
+        proxy().set_forwarded_for(&mut request);
+        assert_eq!(request.headers()[FORWARDED_FOR], "10.0.0.1");
+
+        let mut request = Request::new(empty_body());
+        request
+            .headers_mut()
+            .insert(FORWARDED_FOR, HeaderValue::from_static("1.2.3.4"));
+        proxy().set_forwarded_for(&mut request);
+        assert!(!request.headers().contains_key(FORWARDED_FOR));
+    }
+
+    #[tokio::test]
+    async fn forwarded_for_of_a_trusted_proxy() {
+        let mut request = request("1.2.3.4", "192.0.2.1");
+        proxy()
+            .trust_forwarded_for()
+            .set_forwarded_for(&mut request);
+        assert_eq!(request.headers()[FORWARDED_FOR], "192.0.2.1, 10.0.0.1");
+    }
+}
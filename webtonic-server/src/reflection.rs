@@ -0,0 +1,32 @@
+//! Support for [gRPC server reflection](https://github.com/grpc/grpc/blob/master/doc/server-reflection.md).
+//!
+//! The `grpc.reflection.v1alpha.ServerReflection` service is built from the encoded
+//! file descriptor sets, that `tonic-build` generates when configured with
+//! `file_descriptor_set_path`.
+//! The resulting service can be [added](crate::Router::add_service) to the router and is
+//! then available over the websocket tunnel like any other service.
+//!
+//! # Example
+//! ```ignore
+//! // In build.rs
+//! tonic_build::configure()
+//!     .file_descriptor_set_path(out_dir.join("helloworld_descriptor.bin"))
+//!     .compile(&["helloworld.proto"], &["."])
+//!     .unwrap();
+//!
+//! // In main.rs
+//! let reflection = webtonic_server::reflection::Builder::configure()
+//!     .register_encoded_file_descriptor_set(tonic::include_file_descriptor_set!(
+//!         "helloworld_descriptor"
+//!     ))
+//!     .build()
+//!     .unwrap();
+//!
+//! webtonic_server::Server::builder()
+//!     .add_service(reflection)
+//!     .add_service(GreeterServer::new(greeter))
+//!     .serve(([127, 0, 0, 1], 8080))
+//!     .await;
+//! ```
+
+pub use tonic_reflection::server::{Builder, Error};
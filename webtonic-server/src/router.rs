@@ -0,0 +1,356 @@
+use core::{
+    fmt,
+    task::{Context, Poll},
+};
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt,
+};
+use http::{request::Request, response::Response};
+use std::{collections::HashMap, error::Error, sync::Arc};
+use tonic::{
+    body::{empty_body, BoxBody},
+    codegen::Never,
+    transport::NamedService,
+    Status,
+};
+use tower_service::Service;
+
+use crate::Server;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// A type erased [`Service`](https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html),
+/// that can be stored in a [`Router`](Router).
+///
+/// Errors returned by the wrapped service are turned into `INTERNAL` statuses.
+#[derive(Clone)]
+pub struct BoxService(
+    Arc<dyn Fn(Request<BoxBody>) -> BoxFuture<'static, Response<BoxBody>> + Send + Sync>,
+);
+
+impl BoxService {
+    /// Wraps a [`Service`](https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html)
+    /// into a [`BoxService`](BoxService).
+    pub fn new<S>(service: S) -> Self
+    where
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        Self(Arc::new(move |request| {
+            let mut service = service.clone();
+            async move {
+                let ready: Result<(), BoxError> = future::poll_fn(|cx| service.poll_ready(cx))
+                    .await
+                    .map_err(Into::into);
+                let response: Result<Response<BoxBody>, BoxError> = match ready {
+                    Ok(()) => service.call(request).await.map_err(Into::into),
+                    Err(e) => Err(e),
+                };
+
+                match response {
+                    Ok(response) => response,
+                    Err(e) => {
+                        log::warn!("service returned error {:?}", e);
+                        Status::internal(e.to_string()).to_http()
+                    }
+                }
+            }
+            .boxed()
+        }))
+    }
+}
+
+impl fmt::Debug for BoxService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BoxService")
+    }
+}
+
+impl Service<Request<BoxBody>> for BoxService {
+    type Response = Response<BoxBody>;
+    type Error = Never;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
+        (self.0)(request).map(Ok).boxed()
+    }
+}
+
+/// A [`Router`](Router) dispatches the calls to the services, that were [added](Router::add_service).
+///
+/// Calls are routed by their path `/<service>/<method>`:
+/// 1. to the method override registered for the full path, if there is one,
+/// 2. to the service registered under `<service>`, if there is one,
+/// 3. to the [fallback](Router::fallback) otherwise, which defaults to [`Unimplemented`](Unimplemented).
+#[derive(Debug, Clone)]
+pub struct Router {
+    pub(crate) server: Server,
+    services: HashMap<String, BoxService>,
+    methods: HashMap<String, BoxService>,
+    fallback: BoxService,
+}
+
+impl Router {
+    pub(crate) fn new(server: Server) -> Self {
+        Self {
+            server,
+            services: HashMap::new(),
+            methods: HashMap::new(),
+            fallback: BoxService::new(Unimplemented),
+        }
+    }
+
+    /// [service]: https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html
+    /// Add a [`Service`][service] to the route (see [example](Server)).
+    ///
+    /// The service is routed by its
+    /// [`NamedService::NAME`](https://docs.rs/tonic/0.6.2/tonic/transport/trait.NamedService.html).
+    /// Adding a service of the same name twice replaces the first one.
+    ///
+    /// # Arguments
+    /// - `service`: the [`Service`][service] to add
+    ///
+    /// # Returns
+    /// - The [`Router`](Router), which includes the old routes and the new service.
+    pub fn add_service<S>(self, service: S) -> Self
+    where
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>>
+            + NamedService
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        self.add_named_service(<S as NamedService>::NAME, service)
+    }
+
+    /// [service]: https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html
+    /// Add a [`Service`][service] to the route, if it is `Some`.
+    ///
+    /// This allows to enable services conditionally, without breaking the builder chain.
+    ///
+    /// # Arguments
+    /// - `service`: the optional [`Service`][service] to add
+    ///
+    /// # Returns
+    /// - The [`Router`](Router), which includes the old routes and the new service, if there was one.
+    pub fn add_optional_service<S>(self, service: Option<S>) -> Self
+    where
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>>
+            + NamedService
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        match service {
+            Some(service) => self.add_service(service),
+            None => self,
+        }
+    }
+
+    /// [service]: https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html
+    /// Add a [`Service`][service] to the route under a name, that is only known at runtime.
+    ///
+    /// # Arguments
+    /// - `name`: the fully qualified name of the gRPC service, e.g. `helloworld.Greeter`
+    /// - `service`: the [`Service`][service] to add
+    ///
+    /// # Returns
+    /// - The [`Router`](Router), which includes the old routes and the new service.
+    pub fn add_named_service<S>(mut self, name: impl Into<String>, service: S) -> Self
+    where
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        self.services.insert(name.into(), BoxService::new(service));
+        self
+    }
+
+    /// [service]: https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html
+    /// Route a single method to a [`Service`][service].
+    ///
+    /// Method overrides take precedence over the services added by name.
+    ///
+    /// # Arguments
+    /// - `path`: the full path of the method, e.g. `/helloworld.Greeter/SayHello`
+    /// - `service`: the [`Service`][service], that handles calls to the method
+    ///
+    /// # Returns
+    /// - The [`Router`](Router), which includes the old routes and the new method.
+    pub fn add_method<S>(mut self, path: impl Into<String>, service: S) -> Self
+    where
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        self.methods.insert(path.into(), BoxService::new(service));
+        self
+    }
+
+    /// [service]: https://docs.rs/tower-service/0.3.0/tower_service/trait.Service.html
+    /// Set the [`Service`][service], that handles all calls, for which there is no route.
+    ///
+    /// # Arguments
+    /// - `service`: the fallback [`Service`][service]
+    ///
+    /// # Returns
+    /// - The [`Router`](Router) with the new fallback.
+    pub fn fallback<S>(mut self, service: S) -> Self
+    where
+        S: Service<Request<BoxBody>, Response = Response<BoxBody>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        self.fallback = BoxService::new(service);
+        self
+    }
+
+    /// Routes a call to its service.
+    pub(crate) fn route(&self, request: Request<BoxBody>) -> BoxFuture<'static, Response<BoxBody>> {
+        let path = request.uri().path();
+        let service = path.split('/').nth(1).unwrap_or_default();
+
+        let route = self
+            .methods
+            .get(path)
+            .or_else(|| self.services.get(service))
+            .unwrap_or(&self.fallback);
+
+        (route.0)(request)
+    }
+}
+
+/// The unimplemented service sends `unimplemented` errors on any request.
+///
+/// This is used as the fallthrough route in gRPC.
+#[derive(Default, Clone, Debug)]
+pub struct Unimplemented;
+
+impl Service<Request<BoxBody>> for Unimplemented {
+    type Response = Response<BoxBody>;
+    type Error = Never;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, _req: Request<BoxBody>) -> Self::Future {
+        future::ok(
+            http::Response::builder()
+                .status(200)
+                .header("grpc-status", "12")
+                .header("content-type", "application/grpc")
+                .body(empty_body())
+                .unwrap(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A service, that names the route it was reached by in the `route` header.
+    #[derive(Clone)]
+    struct Route(&'static str);
+
+    impl NamedService for Route {
+        const NAME: &'static str = "test.Route";
+    }
+
+    impl Service<Request<BoxBody>> for Route {
+        type Response = Response<BoxBody>;
+        type Error = Never;
+        type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Ok(()).into()
+        }
+
+        fn call(&mut self, _req: Request<BoxBody>) -> Self::Future {
+            future::ok(
+                http::Response::builder()
+                    .header("route", self.0)
+                    .header("grpc-status", "0")
+                    .body(empty_body())
+                    .unwrap(),
+            )
+        }
+    }
+
+    fn router() -> Router {
+        Server::builder().router()
+    }
+
+    /// Routes a call to `path` and returns the name of the route and the gRPC status.
+    async fn route(router: &Router, path: &str) -> (Option<String>, String) {
+        let request = Request::post(path).body(empty_body()).unwrap();
+        let response = router.route(request).await;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+
+        (header("route"), header("grpc-status").unwrap())
+    }
+
+    #[tokio::test]
+    async fn method_overrides_service() {
+        let router = router()
+            .add_service(Route("service"))
+            .add_method("/test.Route/Override", Route("method"));
+
+        let (route_name, _) = route(&router, "/test.Route/Override").await;
+        assert_eq!(route_name.as_deref(), Some("method"));
+        let (route_name, _) = route(&router, "/test.Route/Other").await;
+        assert_eq!(route_name.as_deref(), Some("service"));
+    }
+
+    #[tokio::test]
+    async fn fallback_for_unknown_paths() {
+        let router = router()
+            .add_service(Route("service"))
+            .fallback(Route("fallback"));
+
+        let (route_name, _) = route(&router, "/test.Unknown/Call").await;
+        assert_eq!(route_name.as_deref(), Some("fallback"));
+        let (route_name, _) = route(&router, "/").await;
+        assert_eq!(route_name.as_deref(), Some("fallback"));
+    }
+
+    #[tokio::test]
+    async fn optional_service() {
+        let router = router().add_optional_service(None::<Route>);
+        let (route_name, status) = route(&router, "/test.Route/Call").await;
+        assert_eq!((route_name, status.as_str()), (None, "12"));
+
+        let router = router.add_optional_service(Some(Route("service")));
+        let (route_name, _) = route(&router, "/test.Route/Call").await;
+        assert_eq!(route_name.as_deref(), Some("service"));
+    }
+
+    #[tokio::test]
+    async fn unimplemented_without_route() {
+        let router = router()
+            .add_named_service("test.Other", Route("other"))
+            .add_method("/test.Route/Override", Route("method"));
+
+        let (route_name, status) = route(&router, "/test.Route/Call").await;
+        assert_eq!((route_name, status.as_str()), (None, "12"));
+    }
+}
@@ -0,0 +1,67 @@
+//! `tracing` instrumentation of connections and calls.
+
+use core::time::Duration;
+use http::request::Request;
+use tonic::body::BoxBody;
+use tracing::{field::Empty, Span};
+
+use crate::ConnectionInfo;
+
+/// Creates the span, that covers the whole lifetime of a connection.
+pub(crate) fn connection_span(info: &ConnectionInfo) -> Span {
+    tracing::info_span!(
+        "connection",
+        connection.id = %info.id(),
+        peer.addr = ?info.remote_addr(),
+    )
+}
+
+/// Creates the span of a single call.
+///
+/// The span is a child of the current connection span.
+/// If the `trace-context` feature is enabled and the call carries a W3C `traceparent`,
+/// the remote span of the client becomes the parent of the span instead.
+pub(crate) fn call_span(request: &Request<BoxBody>, service: &str, method: &str) -> Span {
+    let span = tracing::info_span!(
+        "call",
+        rpc.service = service,
+        rpc.method = method,
+        rpc.grpc.status_code = Empty,
+        duration_ms = Empty,
+    );
+
+    #[cfg(feature = "trace-context")]
+    {
+        use opentelemetry::{
+            propagation::TextMapPropagator, sdk::propagation::TraceContextPropagator,
+        };
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let context = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+        span.set_parent(context);
+    }
+    #[cfg(not(feature = "trace-context"))]
+    let _ = request;
+
+    span
+}
+
+/// Records the outcome of a call on its span.
+pub(crate) fn record_call(span: &Span, code: i32, duration: Duration) {
+    span.record("rpc.grpc.status_code", code);
+    span.record("duration_ms", duration.as_secs_f64() * 1000.0);
+}
+
+#[cfg(feature = "trace-context")]
+struct HeaderExtractor<'a>(&'a http::header::HeaderMap);
+
+#[cfg(feature = "trace-context")]
+impl<'a> opentelemetry::propagation::Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}